@@ -1,13 +1,337 @@
+// anchor 0.31's #[program] emits its IDL handlers (which still call the
+// deprecated AccountInfo::realloc) as a `__private` module next to the program
+// module, so the crate root is the only item an allow can be attached to
+#![allow(deprecated)]
+
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::solana_program::{ed25519_program, sysvar};
+use anchor_lang::system_program;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
 
 declare_id!("Ayt8CLKegBbfHHGkFmMqutNepCAfWzNdo3zPxdqLnQws");
 
+/// Stand-in mint for lamport balances, no real token mint can live at this address
+pub const SOL_MINT: Pubkey = Pubkey::new_from_array([0; 32]);
+
+/// Upper bound on players in a single match, sizes the Match account
+pub const MAX_PLAYERS: usize = 16;
+
+/// Upper bound on settlement authorities kept in the Config account
+pub const MAX_AUTHORITIES: usize = 10;
+
+/// Upper bound on game server keys kept in the Config account
+pub const MAX_GAME_SERVERS: usize = 10;
+
+/// Layout version of Config, stored so a later upgrade can tell layouts apart
+pub const CONFIG_VERSION: u8 = 1;
+
+/// Prefix of every message signed by a game server, keeps result signatures
+/// from being valid for anything else signed with the same key
+pub const RESULT_DOMAIN: &[u8] = b"spelltroum:match-result";
+
+/// Upper bounds on the teams of a team match and on the members of one team
+pub const MAX_TEAMS: usize = 8;
+pub const MAX_TEAM_SIZE: usize = 4;
+
+/// Upper bound on the players of a tournament bracket, a power of two
+pub const MAX_TOURNAMENT_PLAYERS: usize = 32;
+
+/// Upper bound on the participants of a league
+pub const MAX_LEAGUE_PLAYERS: usize = 16;
+
+/// Most games a league can hold: a full round robin of `MAX_LEAGUE_PLAYERS`,
+/// byes included
+pub const MAX_LEAGUE_GAMES: usize = MAX_LEAGUE_PLAYERS * (MAX_LEAGUE_PLAYERS - 1) / 2;
+
+/// League points for a win, a draw and a bye
+pub const WIN_POINTS: u16 = 3;
+pub const DRAW_POINTS: u16 = 1;
+pub const BYE_POINTS: u16 = 3;
+
+/// Longest lifetime of a session key, in seconds
+pub const MAX_SESSION_DURATION: i64 = 7 * 24 * 60 * 60;
+
+/// Default time the arbiter has after a match deadline to rule on a dispute, in seconds
+pub const DEFAULT_ARBITER_WINDOW: i64 = 7 * 24 * 60 * 60;
+
+/// Prefix of every entry consent signed by a player
+pub const CONSENT_DOMAIN: &[u8] = b"spelltroum:entry-consent";
+
+/// 100% expressed in basis points
+pub const MAX_BPS: u16 = 10_000;
+
 #[program]
 pub mod spelltroum_tournament {
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        msg!("Greetings from: {:?}", ctx.program_id);
+    /// Creates the Config. Only the program's upgrade authority can call it,
+    /// so nobody can front-run the deployment and take over the admin role.
+    pub fn initialize(
+        ctx: Context<Initialize>,
+        fee_bps: u16,
+        fee_authority: Pubkey,
+    ) -> Result<()> {
+        require!(fee_bps <= MAX_BPS, CustomError::InvalidFeeBps);
+
+        let config = &mut ctx.accounts.config;
+        config.version = CONFIG_VERSION;
+        config.admin = ctx.accounts.admin.key();
+        config.settlement_authorities = Vec::new();
+        config.game_servers = Vec::new();
+        config.quorum = 0;
+        config.fee_bps = fee_bps;
+        config.fee_authority = fee_authority;
+        config.dust_policy = DustPolicy::Treasury;
+        config.dispute_window = 0;
+        config.arbiter = config.admin;
+        config.arbiter_window = DEFAULT_ARBITER_WINDOW;
+        config.market_fee_bps = 0;
+        config.bump = ctx.bumps.config;
+
+        emit!(ConfigInitialized {
+            admin: config.admin,
+            fee_bps,
+            fee_authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn add_settlement_authority(
+        ctx: Context<UpdateConfig>,
+        authority: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(
+            !config.settlement_authorities.contains(&authority),
+            CustomError::AuthorityAlreadyAdded
+        );
+        require!(
+            config.settlement_authorities.len() < MAX_AUTHORITIES,
+            CustomError::TooManyAuthorities
+        );
+        config.settlement_authorities.push(authority);
+
+        emit!(SettlementAuthorityAdded { authority });
+
+        Ok(())
+    }
+
+    pub fn remove_settlement_authority(
+        ctx: Context<UpdateConfig>,
+        authority: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let index = config
+            .settlement_authorities
+            .iter()
+            .position(|a| *a == authority)
+            .ok_or(CustomError::AuthorityNotFound)?;
+        config.settlement_authorities.remove(index);
+
+        emit!(SettlementAuthorityRemoved { authority });
+
+        Ok(())
+    }
+
+    pub fn add_game_server(ctx: Context<UpdateConfig>, server: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(
+            !config.game_servers.contains(&server),
+            CustomError::GameServerAlreadyAdded
+        );
+        require!(
+            config.game_servers.len() < MAX_GAME_SERVERS,
+            CustomError::TooManyGameServers
+        );
+        config.game_servers.push(server);
+
+        emit!(GameServerAdded { server });
+
+        Ok(())
+    }
+
+    pub fn remove_game_server(ctx: Context<UpdateConfig>, server: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let index = config
+            .game_servers
+            .iter()
+            .position(|s| *s == server)
+            .ok_or(CustomError::GameServerNotFound)?;
+        config.game_servers.remove(index);
+        require!(
+            config.quorum as usize <= config.game_servers.len(),
+            CustomError::QuorumUnreachable
+        );
+
+        emit!(GameServerRemoved { server });
+
+        Ok(())
+    }
+
+    /// Sets M, the number of registered game servers that have to attest
+    /// the same result before a quorum-mode match is paid out
+    pub fn set_quorum(ctx: Context<UpdateConfig>, quorum: u8) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(
+            quorum > 0 && quorum as usize <= config.game_servers.len(),
+            CustomError::QuorumUnreachable
+        );
+        config.quorum = quorum;
+
+        emit!(QuorumUpdated {
+            quorum,
+            servers: config.game_servers.len() as u8,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_payout_preset(
+        ctx: Context<SetPayoutPreset>,
+        preset_id: u16,
+        payout_bps: Vec<u16>,
+    ) -> Result<()> {
+        validate_payout_table(&payout_bps)?;
+
+        let preset = &mut ctx.accounts.payout_preset;
+        preset.preset_id = preset_id;
+        preset.payout_bps = payout_bps.clone();
+        preset.bump = ctx.bumps.payout_preset;
+
+        emit!(PayoutPresetUpdated {
+            preset_id,
+            payout_bps,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_dust_policy(ctx: Context<UpdateConfig>, dust_policy: DustPolicy) -> Result<()> {
+        ctx.accounts.config.dust_policy = dust_policy;
+
+        emit!(DustPolicyUpdated { dust_policy });
+
+        Ok(())
+    }
+
+    /// Sets how many slots a proposed result stays open to disputes, who
+    /// resolves them and how long after the match deadline the arbiter has
+    /// to rule before the players can be refunded
+    pub fn set_dispute_config(
+        ctx: Context<UpdateConfig>,
+        dispute_window: u64,
+        arbiter: Pubkey,
+        arbiter_window: i64,
+    ) -> Result<()> {
+        require!(arbiter_window >= 0, CustomError::InvalidDeadline);
+
+        let config = &mut ctx.accounts.config;
+        config.dispute_window = dispute_window;
+        config.arbiter = arbiter;
+        config.arbiter_window = arbiter_window;
+
+        emit!(DisputeConfigUpdated {
+            dispute_window,
+            arbiter,
+            arbiter_window,
+        });
+
+        Ok(())
+    }
+
+    /// Hands the admin role over to `admin`
+    pub fn set_admin(ctx: Context<UpdateConfig>, admin: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let previous = config.admin;
+        config.admin = admin;
+
+        emit!(AdminUpdated { previous, admin });
+
+        Ok(())
+    }
+
+    /// Sets the fee taken from spectator betting pools of new markets
+    pub fn set_market_fee(ctx: Context<UpdateConfig>, market_fee_bps: u16) -> Result<()> {
+        require!(market_fee_bps <= MAX_BPS, CustomError::InvalidFeeBps);
+
+        ctx.accounts.config.market_fee_bps = market_fee_bps;
+
+        emit!(MarketFeeUpdated { market_fee_bps });
+
+        Ok(())
+    }
+
+    pub fn set_fee(ctx: Context<UpdateConfig>, fee_bps: u16, fee_authority: Pubkey) -> Result<()> {
+        require!(fee_bps <= MAX_BPS, CustomError::InvalidFeeBps);
+
+        let config = &mut ctx.accounts.config;
+        config.fee_bps = fee_bps;
+        config.fee_authority = fee_authority;
+
+        emit!(FeeUpdated {
+            fee_bps,
+            fee_authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_treasury<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawTreasury<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, CustomError::InvalidAmount);
+        let treasury = &mut ctx.accounts.treasury;
+        require!(treasury.amount >= amount, CustomError::InsufficientBalance);
+        treasury.amount -= amount;
+
+        transfer_from_vault(
+            &ctx.accounts.vault,
+            ctx.accounts.destination.to_account_info(),
+            &ctx.accounts.system_program,
+            ctx.bumps.vault,
+            amount,
+        )?;
+
+        emit!(TreasuryWithdrawn {
+            mint: SOL_MINT,
+            amount,
+            destination: ctx.accounts.destination.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_treasury_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawTreasuryToken<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, CustomError::InvalidAmount);
+        let treasury = &mut ctx.accounts.treasury;
+        require!(treasury.amount >= amount, CustomError::InsufficientBalance);
+        treasury.amount -= amount;
+
+        transfer_tokens_from_vault(
+            &ctx.accounts.vault,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.mint,
+            ctx.accounts.destination.to_account_info(),
+            &ctx.accounts.token_program,
+            ctx.bumps.vault,
+            amount,
+        )?;
+
+        emit!(TreasuryWithdrawn {
+            mint: ctx.accounts.mint.key(),
+            amount,
+            destination: ctx.accounts.destination.key(),
+        });
+
         Ok(())
     }
 
@@ -15,14 +339,34 @@ pub mod spelltroum_tournament {
         ctx: Context<'_, '_, '_, 'info, Deposit<'info>>,
         amount: u64,
     ) -> Result<()> {
+        require!(amount > 0, CustomError::InvalidAmount);
+
+        // the vault is a plain system account, so it has to stay rent-exempt;
+        // whoever deposits into an empty vault also covers the rent reserve
+        let rent_reserve = Rent::get()?.minimum_balance(0);
+        let shortfall = rent_reserve.saturating_sub(ctx.accounts.vault.lamports());
+        let lamports = amount
+            .checked_add(shortfall)
+            .ok_or(CustomError::MathOverflow)?;
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.owner.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                },
+            ),
+            lamports,
+        )?;
+
         let player = &mut ctx.accounts.player_balance;
         // this is how we get the bump
         let bump = ctx.bumps.player_balance;
 
-        require!(amount > 0, CustomError::InvalidAmount);
-
-        // initialize owner and bump
+        // initialize owner, mint and bump
         player.owner = ctx.accounts.owner.key();
+        player.mint = SOL_MINT;
         player.bump = bump;
 
         // update balance
@@ -33,177 +377,4729 @@ pub mod spelltroum_tournament {
 
         emit!(Deposited {
             user: ctx.accounts.owner.key(),
+            mint: SOL_MINT,
             amount,
         });
 
         Ok(())
     }
 
-    pub fn withdraw<'info>(ctx: Context<'_, '_, '_, 'info, Withdraw<'info>>) -> Result<()> {
-        let player = &mut ctx.accounts.player_balance;
+    pub fn withdraw<'info>(
+        ctx: Context<'_, '_, '_, 'info, Withdraw<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        let balance = ctx.accounts.player_balance.amount;
+        require!(balance > 0, CustomError::NothingToWithdraw);
+        require!(amount > 0, CustomError::InvalidAmount);
+        require!(balance >= amount, CustomError::InsufficientBalance);
+
+        // pay out to the owner's wallet unless another destination is given
+        let destination = match &ctx.accounts.destination {
+            Some(destination) => destination.to_account_info(),
+            None => ctx.accounts.owner.to_account_info(),
+        };
 
-        let amount = player.amount;
-        require!(amount > 0, CustomError::NothingToWithdraw);
+        transfer_from_vault(
+            &ctx.accounts.vault,
+            destination.clone(),
+            &ctx.accounts.system_program,
+            ctx.bumps.vault,
+            amount,
+        )?;
 
-        player.amount = 0;
+        ctx.accounts.player_balance.amount = balance - amount;
 
         emit!(Withdrawn {
             user: ctx.accounts.owner.key(),
+            mint: SOL_MINT,
             amount,
+            destination: destination.key(),
         });
 
         Ok(())
     }
 
-    // still a work in progress, so it's currently open to the public
-    // in the future it will be called only by a trusted authority
-    pub fn settle_match<'info>(
-        ctx: Context<'_, '_, 'info, 'info, SettleMatch<'info>>,
-        match_id: String,
-        players: Vec<Pubkey>,
-        winners: Vec<Pubkey>,
-        entry_fee: u64,
+    pub fn deposit_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, DepositToken<'info>>,
+        amount: u64,
     ) -> Result<()> {
-        require!(!winners.is_empty(), CustomError::NoWinnersProvided);
+        require!(amount > 0, CustomError::InvalidAmount);
 
-        // This is to prevent accidentally settling the match twice
-        require!(
-            ctx.accounts
-                .match_history
-                .settled_matches
-                .iter()
-                .any(|m| m == &match_id)
-                == false,
-            CustomError::MatchAlreadySettled
-        );
+        let vault_before = ctx.accounts.vault_token_account.amount;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.owner_token_account.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.vault_token_account.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
 
-        let prize_pool = entry_fee
-            .checked_mul(players.len() as u64)
+        // Token-2022 transfer fees can shave the amount, so only credit
+        // what actually landed in the vault
+        ctx.accounts.vault_token_account.reload()?;
+        let received = ctx
+            .accounts
+            .vault_token_account
+            .amount
+            .checked_sub(vault_before)
             .ok_or(CustomError::MathOverflow)?;
-        let share = prize_pool
-            .checked_div(winners.len() as u64)
+        require!(received > 0, CustomError::InvalidAmount);
+
+        let player = &mut ctx.accounts.player_balance;
+        player.owner = ctx.accounts.owner.key();
+        player.mint = ctx.accounts.mint.key();
+        player.bump = ctx.bumps.player_balance;
+        player.amount = player
+            .amount
+            .checked_add(received)
             .ok_or(CustomError::MathOverflow)?;
 
-        // Deduct entry fees
-        for (i, player_key) in players.iter().enumerate() {
-            let balance_info = &ctx.remaining_accounts[i];
-            let mut balance: Account<PlayerBalance> = Account::try_from(balance_info)?;
-            require!(
-                balance.owner == *player_key,
-                CustomError::InvalidPlayerAccount
-            );
-            require!(
-                balance.amount >= entry_fee,
-                CustomError::InsufficientBalance
-            );
-            balance.amount -= entry_fee;
-        }
+        emit!(Deposited {
+            user: ctx.accounts.owner.key(),
+            mint: ctx.accounts.mint.key(),
+            amount: received,
+        });
 
-        // Distribute winnings
-        for (i, winner_key) in winners.iter().enumerate() {
-            let balance_info = &ctx.remaining_accounts[players.len() + i];
-            let mut balance: Account<PlayerBalance> = Account::try_from(balance_info)?;
-            require!(
-                balance.owner == *winner_key,
-                CustomError::InvalidWinnerAccount
-            );
-            balance.amount = balance
-                .amount
-                .checked_add(share)
-                .ok_or(CustomError::MathOverflow)?;
-        }
+        Ok(())
+    }
 
-        // Add the match to history to prevent settling it twice
-        let match_history = &mut ctx.accounts.match_history;
-        if match_history.settled_matches.len() < 100 {
-            match_history.settled_matches.push(match_id.clone());
-        } else {
-            let index = match_history.current_index as usize % 100;
-            match_history.settled_matches[index] = match_id.clone();
-        }
-        match_history.current_index = (match_history.current_index + 1) % 100;
+    pub fn withdraw_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawToken<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        let balance = ctx.accounts.player_balance.amount;
+        require!(balance > 0, CustomError::NothingToWithdraw);
+        require!(amount > 0, CustomError::InvalidAmount);
+        require!(balance >= amount, CustomError::InsufficientBalance);
+
+        transfer_tokens_from_vault(
+            &ctx.accounts.vault,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.mint,
+            ctx.accounts.destination.to_account_info(),
+            &ctx.accounts.token_program,
+            ctx.bumps.vault,
+            amount,
+        )?;
+
+        ctx.accounts.player_balance.amount = balance - amount;
+
+        emit!(Withdrawn {
+            user: ctx.accounts.owner.key(),
+            mint: ctx.accounts.mint.key(),
+            amount,
+            destination: ctx.accounts.destination.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn create_match(
+        ctx: Context<CreateMatch>,
+        match_id: String,
+        mint: Pubkey,
+        stake_rule: StakeRule,
+        settlement_mode: SettlementMode,
+        deadline: i64,
+        seats: u8,
+    ) -> Result<()> {
+        let entry_fee = match stake_rule {
+            StakeRule::Fixed { entry_fee } => {
+                require!(entry_fee > 0, CustomError::InvalidAmount);
+                entry_fee
+            }
+            StakeRule::Variable { min, max, .. } => {
+                require!(min > 0 && max >= min, CustomError::InvalidStakeRule);
+                min
+            }
+        };
+        require!(
+            seats == 0 || (2..=MAX_PLAYERS as u8).contains(&seats),
+            CustomError::InvalidSeatCount
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(deadline > now, CustomError::InvalidDeadline);
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.match_id_hash = hash(match_id.as_bytes()).to_bytes();
+        match_account.authority = ctx.accounts.authority.key();
+        match_account.mint = mint;
+        match_account.entry_fee = entry_fee;
+        match_account.stake_rule = stake_rule;
+        // the rake is fixed when the match opens, so players know what they join
+        match_account.fee_bps = ctx.accounts.config.fee_bps;
+        match_account.escrow = 0;
+        match_account.status = MatchStatus::Open;
+        match_account.settlement_mode = settlement_mode;
+        match_account.players = Vec::new();
+        match_account.stakes = Vec::new();
+        match_account.attestations = Vec::new();
+        match_account.pending_result = None;
+        match_account.created_at = now;
+        match_account.deadline = deadline;
+        match_account.seats = seats;
+        match_account.has_market = false;
+        match_account.closed_at = 0;
+        match_account.bump = ctx.bumps.match_account;
 
-        emit!(MatchSettled {
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.mint = mint;
+        treasury.bump = ctx.bumps.treasury;
+
+        emit!(MatchCreated {
             match_id,
-            total_pool: prize_pool,
+            authority: ctx.accounts.authority.key(),
+            mint,
+            entry_fee,
+            stake_rule,
+            settlement_mode,
+            deadline,
+            seats,
         });
 
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct Initialize {}
+    /// Lets the game client act for the player without a wallet prompt per
+    /// transaction. The session key can only join matches, tournaments and
+    /// leagues in one mint, until `expires_at` and for at most `spend_cap` in
+    /// total; it can never withdraw.
+    pub fn create_session_key(
+        ctx: Context<CreateSessionKey>,
+        session_signer: Pubkey,
+        mint: Pubkey,
+        expires_at: i64,
+        spend_cap: u64,
+        scope: SessionScope,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            expires_at > now && expires_at - now <= MAX_SESSION_DURATION,
+            CustomError::InvalidSessionExpiry
+        );
+        require!(spend_cap > 0, CustomError::InvalidAmount);
 
-#[derive(Accounts)]
-pub struct Deposit<'info> {
-    /// The wallet of the player, will pay rent if needed
-    #[account(mut)]
-    pub owner: Signer<'info>,
+        let session = &mut ctx.accounts.session_key;
+        session.owner = ctx.accounts.owner.key();
+        session.signer = session_signer;
+        session.mint = mint;
+        session.expires_at = expires_at;
+        session.spend_cap = spend_cap;
+        session.spent = 0;
+        session.scope = scope;
+        session.bump = ctx.bumps.session_key;
 
-    /// Player's balance account, created and initialized on first deposit
-    #[account(
-        init_if_needed,
-        payer = owner,
-        space = 8 + std::mem::size_of::<PlayerBalance>(),
-        seeds = [b"player", owner.key().as_ref()],
-        bump
-    )]
-    pub player_balance: Account<'info, PlayerBalance>,
-    pub system_program: Program<'info, System>,
-}
+        emit!(SessionKeyCreated {
+            owner: session.owner,
+            signer: session_signer,
+            mint,
+            expires_at,
+            spend_cap,
+            scope,
+        });
 
-#[derive(Accounts)]
-pub struct Withdraw<'info> {
-    #[account(mut)]
-    pub owner: Signer<'info>,
+        Ok(())
+    }
 
-    #[account(
-        mut,
-        seeds = [b"player", owner.key().as_ref()],
-        bump,
-        has_one = owner
-    )]
-    pub player_balance: Account<'info, PlayerBalance>,
-}
+    /// Closes a session key before it expires, returning its rent to the owner
+    pub fn revoke_session_key(ctx: Context<RevokeSessionKey>) -> Result<()> {
+        emit!(SessionKeyRevoked {
+            owner: ctx.accounts.owner.key(),
+            signer: ctx.accounts.session_key.signer,
+        });
 
-#[derive(Accounts)]
-pub struct SettleMatch<'info> {
-    #[account(mut, has_one = authority)]
-    pub match_history: Account<'info, MatchHistory>,
-    pub authority: Signer<'info>,
-}
+        Ok(())
+    }
 
-#[account]
-pub struct PlayerBalance {
-    pub owner: Pubkey,
-    pub amount: u64,
-    pub bump: u8,
-}
+    /// Creates the stats account of `player` in `mint`; settlements skip the
+    /// players who have none. Anyone can pay for it; the stats start at zero.
+    pub fn create_player_stats(
+        ctx: Context<CreatePlayerStats>,
+        player: Pubkey,
+        mint: Pubkey,
+    ) -> Result<()> {
+        let stats = &mut ctx.accounts.player_stats;
+        stats.player = player;
+        stats.mint = mint;
+        stats.matches_played = 0;
+        stats.wins = 0;
+        stats.total_wagered = 0;
+        stats.total_won = 0;
+        stats.biggest_win = 0;
+        stats.current_streak = 0;
+        stats.best_streak = 0;
+        stats.bump = ctx.bumps.player_stats;
 
-#[account]
-pub struct MatchHistory {
-    pub authority: Pubkey,
-    pub settled_matches: Vec<String>,
-    pub current_index: u16,
-}
+        emit!(PlayerStatsCreated { player, mint });
 
-#[event]
-pub struct Deposited {
-    pub user: Pubkey,
-    pub amount: u64,
-}
+        Ok(())
+    }
 
-#[event]
-pub struct Withdrawn {
-    pub user: Pubkey,
-    pub amount: u64,
-}
+    /// Joins a match signed by the player or by one of their session keys.
+    /// `max_entry_fee` and `nonce` are the player's consent: the fee is never taken above the
+    /// maximum they approved, and each nonce can be used only once. In a variable-stake
+    /// match the player stakes exactly `max_entry_fee`.
+    pub fn join_match(
+        ctx: Context<JoinMatch>,
+        match_id: String,
+        max_entry_fee: u64,
+        nonce: u64,
+    ) -> Result<()> {
+        let player = ctx.accounts.player.key();
+        let stake = ctx.accounts.match_account.stake_for(max_entry_fee)?;
+        authorize_signer(
+            ctx.accounts.signer.key(),
+            player,
+            ctx.accounts.session_key.as_deref_mut(),
+            ctx.accounts.match_account.mint,
+            stake,
+        )?;
+        check_not_bettor(
+            &ctx.accounts.match_account,
+            ctx.accounts.market.as_ref(),
+            ctx.accounts.bet.as_deref(),
+            player,
+        )?;
 
-#[event]
-pub struct MatchSettled {
+        escrow_entry_fee(
+            match_id,
+            &mut ctx.accounts.match_account,
+            &mut ctx.accounts.player_balance,
+            player,
+            max_entry_fee,
+            nonce,
+        )
+    }
+
+    /// Joins a match on behalf of a player who signed an EntryConsent off-chain.
+    /// The signature is checked by an Ed25519 precompile instruction placed
+    /// right before this one, so the backend can submit it for the player.
+    pub fn join_match_with_consent(
+        ctx: Context<JoinMatchWithConsent>,
+        consent: EntryConsent,
+    ) -> Result<()> {
+        let message = [CONSENT_DOMAIN, consent.try_to_vec()?.as_slice()].concat();
+        let signer = ed25519_signer(&ctx.accounts.instructions, &message)?;
+        require_keys_eq!(signer, consent.player, CustomError::InvalidConsent);
+        check_not_bettor(
+            &ctx.accounts.match_account,
+            ctx.accounts.market.as_ref(),
+            ctx.accounts.bet.as_deref(),
+            consent.player,
+        )?;
+
+        escrow_entry_fee(
+            consent.match_id,
+            &mut ctx.accounts.match_account,
+            &mut ctx.accounts.player_balance,
+            consent.player,
+            consent.max_entry_fee,
+            consent.nonce,
+        )
+    }
+
+    /// Leaves a lobby that has not started yet, refunding the entry fee in full
+    pub fn leave_match(ctx: Context<LeaveMatch>, match_id: String) -> Result<()> {
+        let player = ctx.accounts.player.key();
+        let match_account = &mut ctx.accounts.match_account;
+        require!(match_account.seats > 0, CustomError::NotALobby);
+        require!(
+            match_account.status == MatchStatus::Open,
+            CustomError::MatchNotOpen
+        );
+        let index = match_account
+            .players
+            .iter()
+            .position(|p| *p == player)
+            .ok_or(CustomError::NotAParticipant)?;
+
+        let refunded = refund_stakes(
+            match_account,
+            |p| *p == player,
+            &[ctx.accounts.player_balance.to_account_info()],
+        )?
+        .total();
+        match_account.players.remove(index);
+        match_account.stakes.remove(index);
+        match_account.escrow -= refunded;
+
+        emit!(PlayerLeft {
+            match_id,
+            player,
+            refunded,
+        });
+
+        Ok(())
+    }
+
+    /// Proposes the result of a match. Nothing is paid out yet: the result
+    /// stays pending for `Config.dispute_window` slots, after which anyone can
+    /// call `finalize_result`.
+    pub fn propose_result(
+        ctx: Context<ProposeResult>,
+        match_id: String,
+        placements: Vec<Placement>,
+        payout_bps: Option<Vec<u16>>,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.match_account.settlement_mode == SettlementMode::Authority,
+            CustomError::WrongSettlementMode
+        );
+
+        let payout_bps = payout_table(payout_bps, ctx.accounts.payout_preset.as_deref())?;
+        propose_placements(
+            match_id,
+            &mut ctx.accounts.match_account,
+            ctx.accounts.authority.key(),
+            placements,
+            Vec::new(),
+            payout_bps,
+            ctx.accounts.config.dispute_window,
+        )
+    }
+
+    /// Proposes the result of a team match. The payout table ranks the teams;
+    /// each team's share is split among its members evenly or by the team's
+    /// weights. Balance accounts follow the members in team order.
+    pub fn propose_team_result(
+        ctx: Context<ProposeResult>,
+        match_id: String,
+        teams: Vec<Team>,
+        payout_bps: Option<Vec<u16>>,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.match_account.settlement_mode == SettlementMode::Authority,
+            CustomError::WrongSettlementMode
+        );
+
+        let payout_bps = payout_table(payout_bps, ctx.accounts.payout_preset.as_deref())?;
+        propose_placements(
+            match_id,
+            &mut ctx.accounts.match_account,
+            ctx.accounts.authority.key(),
+            Vec::new(),
+            teams,
+            payout_bps,
+            ctx.accounts.config.dispute_window,
+        )
+    }
+
+    /// Proposes a match outcome without placements: a draw splits the pool
+    /// among all players, a no-contest refunds everyone and a void refunds
+    /// everyone but the flagged cheaters, whose entry fees go to the treasury.
+    pub fn propose_outcome(
+        ctx: Context<ProposeResult>,
+        match_id: String,
+        outcome: MatchOutcome,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.match_account.settlement_mode == SettlementMode::Authority,
+            CustomError::WrongSettlementMode
+        );
+
+        propose_unranked(
+            match_id,
+            &mut ctx.accounts.match_account,
+            ctx.accounts.authority.key(),
+            outcome,
+            ctx.accounts.config.dispute_window,
+        )
+    }
+
+    /// Proposes a result signed by a registered game server. The signature is
+    /// checked by an Ed25519 precompile instruction placed right before this
+    /// one, so any relayer can submit it without an authority key.
+    pub fn propose_result_signed(
+        ctx: Context<ProposeResultSigned>,
+        result: MatchResult,
+    ) -> Result<()> {
+        let message = [RESULT_DOMAIN, result.try_to_vec()?.as_slice()].concat();
+        let server = ed25519_signer(&ctx.accounts.instructions, &message)?;
+        require!(
+            ctx.accounts.config.game_servers.contains(&server),
+            CustomError::UnknownGameServer
+        );
+        require!(
+            ctx.accounts.match_account.settlement_mode == SettlementMode::Authority,
+            CustomError::WrongSettlementMode
+        );
+        check_result(&result, &ctx.accounts.match_account)?;
+
+        propose_match_result(
+            &mut ctx.accounts.match_account,
+            server,
+            result,
+            ctx.accounts.config.dispute_window,
+        )
+    }
+
+    /// Records one game server's attestation of a match result. The result is
+    /// proposed as soon as `Config.quorum` servers attested the same result
+    /// hash, and paid out by `finalize_result` like any other proposal.
+    pub fn attest_result(ctx: Context<AttestResult>, result: MatchResult) -> Result<()> {
+        let server = ctx.accounts.server.key();
+        let quorum = ctx.accounts.config.quorum;
+        require!(
+            ctx.accounts.config.game_servers.contains(&server),
+            CustomError::UnknownGameServer
+        );
+        require!(quorum > 0, CustomError::QuorumUnreachable);
+
+        let match_account = &mut ctx.accounts.match_account;
+        require!(
+            match_account.settlement_mode == SettlementMode::Quorum,
+            CustomError::WrongSettlementMode
+        );
+        match_account.require_in_play()?;
+        // attestations of servers removed from Config since no longer count
+        let game_servers = &ctx.accounts.config.game_servers;
+        match_account
+            .attestations
+            .retain(|a| game_servers.contains(&a.server));
+        require!(
+            !match_account.attestations.iter().any(|a| a.server == server),
+            CustomError::AlreadyAttested
+        );
+        require!(
+            match_account.attestations.len() < MAX_GAME_SERVERS,
+            CustomError::TooManyGameServers
+        );
+        check_result(&result, match_account)?;
+
+        let result_hash = hash(&result.try_to_vec()?).to_bytes();
+        // a server disagreeing with an earlier attestation is surfaced to
+        // support, but does not block the other servers from reaching quorum
+        if let Some(conflict) = match_account
+            .attestations
+            .iter()
+            .find(|a| a.result_hash != result_hash)
+        {
+            emit!(ResultConflict {
+                match_id: result.match_id.clone(),
+                server,
+                result_hash,
+                conflicting_server: conflict.server,
+                conflicting_hash: conflict.result_hash,
+            });
+        }
+        match_account.attestations.push(Attestation {
+            server,
+            result_hash,
+        });
+
+        let attestations = match_account
+            .attestations
+            .iter()
+            .filter(|a| a.result_hash == result_hash)
+            .count() as u8;
+        emit!(ResultAttested {
+            match_id: result.match_id.clone(),
+            server,
+            result_hash,
+            attestations,
+        });
+        if attestations < quorum {
+            return Ok(());
+        }
+
+        emit!(QuorumReached {
+            match_id: result.match_id.clone(),
+            result_hash,
+            attestations,
+        });
+        propose_match_result(
+            &mut ctx.accounts.match_account,
+            server,
+            result,
+            ctx.accounts.config.dispute_window,
+        )
+    }
+
+    /// Disputes a pending result. Only players of the match can dispute, and
+    /// only while the dispute window is open; the payout stays frozen until
+    /// the arbiter resolves the dispute.
+    pub fn dispute_result(
+        ctx: Context<DisputeResult>,
+        match_id: String,
+        evidence_hash: [u8; 32],
+    ) -> Result<()> {
+        let disputer = ctx.accounts.disputer.key();
+        let match_account = &mut ctx.accounts.match_account;
+        require!(
+            match_account.players.contains(&disputer),
+            CustomError::NotAParticipant
+        );
+        require!(
+            match_account.status == MatchStatus::Proposed,
+            CustomError::NoPendingResult
+        );
+        let slot = Clock::get()?.slot;
+        let pending = match_account
+            .pending_result
+            .as_mut()
+            .ok_or(CustomError::NoPendingResult)?;
+        require!(!pending.arbitrated, CustomError::AlreadyArbitrated);
+        require!(
+            slot < pending.finalizable_at,
+            CustomError::DisputeWindowClosed
+        );
+
+        pending.dispute = Some(Dispute {
+            disputer,
+            evidence_hash,
+            disputed_at: slot,
+        });
+        match_account.status = MatchStatus::Disputed;
+
+        emit!(ResultDisputed {
+            match_id,
+            disputer,
+            evidence_hash,
+            slot,
+        });
+
+        Ok(())
+    }
+
+    /// Settles a dispute: the arbiter either upholds the proposed result or
+    /// replaces it with a corrected one. Either way the decision is final and
+    /// the result can be finalized right away.
+    pub fn resolve_dispute(
+        ctx: Context<ResolveDispute>,
+        match_id: String,
+        resolution: DisputeResolution,
+    ) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+        require!(
+            match_account.status == MatchStatus::Disputed,
+            CustomError::NotDisputed
+        );
+
+        let mut pending = match_account
+            .pending_result
+            .take()
+            .ok_or(CustomError::NoPendingResult)?;
+        let overturned = match resolution {
+            DisputeResolution::Uphold => false,
+            DisputeResolution::Overturn {
+                outcome: MatchOutcome::Ranked,
+                placements,
+                teams,
+                payout_bps,
+            } => {
+                pending.placements =
+                    result_placements(match_account, placements, &teams, &payout_bps)?;
+                pending.outcome = MatchOutcome::Ranked;
+                pending.teams = teams;
+                pending.payout_bps = payout_bps;
+                true
+            }
+            // a draw, no-contest or void replaces the placements altogether
+            DisputeResolution::Overturn {
+                outcome,
+                placements,
+                teams,
+                payout_bps,
+            } => {
+                require!(
+                    placements.is_empty() && teams.is_empty() && payout_bps.is_empty(),
+                    CustomError::InvalidOutcome
+                );
+                check_outcome(match_account, &outcome)?;
+                pending.placements = Vec::new();
+                pending.outcome = outcome;
+                pending.teams = Vec::new();
+                pending.payout_bps = Vec::new();
+                true
+            }
+        };
+        pending.arbitrated = true;
+        pending.finalizable_at = Clock::get()?.slot;
+
+        emit!(DisputeResolved {
+            match_id,
+            arbiter: ctx.accounts.arbiter.key(),
+            overturned,
+            outcome: pending.outcome.clone(),
+            placements: pending.placements.clone(),
+            teams: pending.teams.clone(),
+            payout_bps: pending.payout_bps.clone(),
+        });
+
+        match_account.pending_result = Some(pending);
+        match_account.status = MatchStatus::Proposed;
+
+        Ok(())
+    }
+
+    /// Pays out the pending result once its dispute window has passed, or
+    /// right after the arbiter resolved a dispute. Permissionless; the balance
+    /// accounts of the placements are passed as remaining accounts.
+    pub fn finalize_result<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FinalizeResult<'info>>,
+        match_id: String,
+    ) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+        require!(
+            match_account.status != MatchStatus::Settled,
+            CustomError::MatchAlreadySettled
+        );
+        require!(
+            match_account.status != MatchStatus::Disputed,
+            CustomError::ResultUnderDispute
+        );
+        require!(
+            match_account.status == MatchStatus::Proposed,
+            CustomError::NoPendingResult
+        );
+        let finalizable_at = match_account
+            .pending_result
+            .as_ref()
+            .ok_or(CustomError::NoPendingResult)?
+            .finalizable_at;
+        require!(
+            Clock::get()?.slot >= finalizable_at,
+            CustomError::DisputeWindowOpen
+        );
+
+        // the spectator market resolves together with the match
+        if match_account.has_market {
+            let market = ctx
+                .accounts
+                .market
+                .as_mut()
+                .ok_or(CustomError::MarketMissing)?;
+            resolve_market(
+                match_id.clone(),
+                market,
+                match_account,
+                &mut ctx.accounts.treasury,
+            )?;
+        }
+
+        settle_pending(
+            match_id,
+            match_account,
+            &mut ctx.accounts.treasury,
+            ctx.accounts.config.dust_policy,
+            ctx.remaining_accounts,
+        )
+    }
+
+    pub fn cancel_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelMatch<'info>>,
+        match_id: String,
+    ) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+        require!(
+            matches!(
+                match_account.status,
+                MatchStatus::Open | MatchStatus::Started
+            ),
+            CustomError::MatchNotOpen
+        );
+
+        let refunded = refund_stakes(match_account, |_| true, ctx.remaining_accounts)?.total();
+        match_account.escrow = 0;
+        match_account.status = MatchStatus::Cancelled;
+        match_account.closed_at = Clock::get()?.unix_timestamp;
+
+        emit!(MatchCancelled {
+            match_id,
+            refunded,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless escape hatch: once the deadline has passed without a
+    /// settlement, anyone can refund the players and close the match for good.
+    /// A disputed match the arbiter never rules on can be expired once
+    /// `Config.arbiter_window` has passed after the deadline as well.
+    pub fn expire_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExpireMatch<'info>>,
+        match_id: String,
+    ) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+        let expires_at = match match_account.status {
+            MatchStatus::Open | MatchStatus::Started => match_account.deadline,
+            // a dispute must not lock the stakes for good
+            MatchStatus::Disputed => match_account
+                .deadline
+                .checked_add(ctx.accounts.config.arbiter_window)
+                .ok_or(CustomError::MathOverflow)?,
+            _ => return err!(CustomError::MatchNotOpen),
+        };
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= expires_at, CustomError::MatchNotExpired);
+
+        let refunded = refund_stakes(match_account, |_| true, ctx.remaining_accounts)?.total();
+        match_account.escrow = 0;
+        match_account.pending_result = None;
+        match_account.status = MatchStatus::Expired;
+        match_account.closed_at = now;
+
+        emit!(MatchExpired {
+            match_id,
+            refunded,
+        });
+
+        Ok(())
+    }
+
+    /// Attaches a parimutuel spectator market to a match. Bets on players are
+    /// taken until `lock_time`; the market resolves when the match result is
+    /// finalized, and is refunded if the match ends without a winner.
+    pub fn create_market(
+        ctx: Context<CreateMarket>,
+        match_id: String,
+        lock_time: i64,
+    ) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+        require!(
+            matches!(
+                match_account.status,
+                MatchStatus::Open | MatchStatus::Started
+            ),
+            CustomError::MatchNotOpen
+        );
+        require!(
+            lock_time > Clock::get()?.unix_timestamp && lock_time <= match_account.deadline,
+            CustomError::InvalidLockTime
+        );
+        match_account.has_market = true;
+
+        let market = &mut ctx.accounts.market;
+        market.match_account = match_account.key();
+        market.mint = match_account.mint;
+        market.lock_time = lock_time;
+        // like the rake, the fee is fixed when the market opens
+        market.fee_bps = ctx.accounts.config.market_fee_bps;
+        market.status = MarketStatus::Open;
+        market.total = 0;
+        market.pools = Vec::new();
+        market.winners = Vec::new();
+        market.winning_pool = 0;
+        market.refund_pool = 0;
+        market.fee = 0;
+        market.paid_out = 0;
+        market.open_bets = 0;
+        market.bump = ctx.bumps.market;
+
+        emit!(MarketCreated {
+            match_id,
+            market: market.key(),
+            lock_time,
+            fee_bps: market.fee_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Stakes `amount` from the bettor's balance on `selection`, a player of
+    /// the match. A team wins for every bet on one of its members. Bets can be
+    /// topped up, but only on the same selection. The players of the match
+    /// themselves cannot bet on it.
+    pub fn place_bet(
+        ctx: Context<PlaceBet>,
+        match_id: String,
+        selection: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, CustomError::InvalidAmount);
+        let match_account = &ctx.accounts.match_account;
+        require!(
+            matches!(
+                match_account.status,
+                MatchStatus::Open | MatchStatus::Started
+            ),
+            CustomError::MatchNotOpen
+        );
+        require!(
+            match_account.players.contains(&selection),
+            CustomError::NotAParticipant
+        );
+        require!(
+            !match_account.players.contains(&ctx.accounts.bettor.key()),
+            CustomError::PlayerCannotBet
+        );
+        let market = &mut ctx.accounts.market;
+        require!(
+            market.status == MarketStatus::Open
+                && Clock::get()?.unix_timestamp < market.lock_time,
+            CustomError::BettingClosed
+        );
+
+        let balance = &mut ctx.accounts.bettor_balance;
+        require!(balance.amount >= amount, CustomError::InsufficientBalance);
+        balance.amount -= amount;
+
+        let bet = &mut ctx.accounts.bet;
+        if bet.amount == 0 {
+            bet.market = market.key();
+            bet.bettor = ctx.accounts.bettor.key();
+            bet.selection = selection;
+            bet.bump = ctx.bumps.bet;
+            market.open_bets = market
+                .open_bets
+                .checked_add(1)
+                .ok_or(CustomError::MathOverflow)?;
+        }
+        require_keys_eq!(bet.selection, selection, CustomError::BetSelectionMismatch);
+        bet.amount = bet
+            .amount
+            .checked_add(amount)
+            .ok_or(CustomError::MathOverflow)?;
+
+        market.total = market
+            .total
+            .checked_add(amount)
+            .ok_or(CustomError::MathOverflow)?;
+        match market.pools.iter_mut().find(|pool| pool.selection == selection) {
+            Some(pool) => {
+                pool.amount = pool
+                    .amount
+                    .checked_add(amount)
+                    .ok_or(CustomError::MathOverflow)?;
+            }
+            None => market.pools.push(SelectionPool { selection, amount }),
+        }
+
+        emit!(BetPlaced {
+            match_id,
+            bettor: bet.bettor,
+            selection,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Pays out a bet once its market is resolved, pro rata to the winning
+    /// pool after the market fee, or refunds it if the market was refunded,
+    /// the match was cancelled or expired, or the selection left the match.
+    /// Anyone can claim for the bettor, so losing bets can be closed too; the
+    /// payout and the rent of the bet account always go to the bettor. The
+    /// last claim sweeps the rounding dust to the treasury.
+    pub fn claim_bet(ctx: Context<ClaimBet>, match_id: String) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        if market.status == MarketStatus::Open {
+            require!(
+                matches!(
+                    ctx.accounts.match_account.status,
+                    MatchStatus::Cancelled | MatchStatus::Expired
+                ),
+                CustomError::MarketNotResolved
+            );
+            market.status = MarketStatus::Refunding;
+        }
+
+        let bet = &ctx.accounts.bet;
+        let left = !ctx.accounts.match_account.players.contains(&bet.selection);
+        let payout = match market.status {
+            MarketStatus::Resolved if market.winners.contains(&bet.selection) => {
+                let pool = market.total - market.refund_pool - market.fee;
+                ((bet.amount as u128 * pool as u128) / market.winning_pool as u128) as u64
+            }
+            MarketStatus::Resolved if !left => 0,
+            _ => bet.amount,
+        };
+        market.paid_out = market
+            .paid_out
+            .checked_add(payout)
+            .ok_or(CustomError::MathOverflow)?;
+        market.open_bets = market
+            .open_bets
+            .checked_sub(1)
+            .ok_or(CustomError::MathOverflow)?;
+        if market.open_bets == 0 {
+            let dust = market
+                .total
+                .checked_sub(market.fee)
+                .and_then(|rest| rest.checked_sub(market.paid_out))
+                .ok_or(CustomError::MathOverflow)?;
+            let treasury = &mut ctx.accounts.treasury;
+            treasury.amount = treasury
+                .amount
+                .checked_add(dust)
+                .ok_or(CustomError::MathOverflow)?;
+        }
+
+        let balance = &mut ctx.accounts.bettor_balance;
+        balance.amount = balance
+            .amount
+            .checked_add(payout)
+            .ok_or(CustomError::MathOverflow)?;
+
+        emit!(BetClaimed {
+            match_id,
+            bettor: bet.bettor,
+            selection: bet.selection,
+            staked: bet.amount,
+            payout,
+        });
+
+        Ok(())
+    }
+
+    /// Opens a single-elimination tournament for up to `max_players` players
+    /// (a power of two). Registration buys into the prize pool, which is paid
+    /// out to the top placements by `payout_bps` once the bracket is done.
+    pub fn create_tournament(
+        ctx: Context<CreateTournament>,
+        tournament_id: String,
+        mint: Pubkey,
+        buy_in: u64,
+        max_players: u8,
+        payout_bps: Vec<u16>,
+    ) -> Result<()> {
+        require!(buy_in > 0, CustomError::InvalidAmount);
+        require!(
+            max_players >= 2
+                && max_players.is_power_of_two()
+                && max_players as usize <= MAX_TOURNAMENT_PLAYERS,
+            CustomError::InvalidBracketSize
+        );
+        validate_payout_table(&payout_bps)?;
+        require!(
+            payout_bps.len() <= max_players as usize,
+            CustomError::InvalidPayoutTable
+        );
+
+        let tournament = &mut ctx.accounts.tournament;
+        tournament.tournament_id_hash = hash(tournament_id.as_bytes()).to_bytes();
+        tournament.authority = ctx.accounts.authority.key();
+        tournament.mint = mint;
+        tournament.buy_in = buy_in;
+        tournament.fee_bps = ctx.accounts.config.fee_bps;
+        tournament.prize_pool = 0;
+        tournament.max_players = max_players;
+        tournament.status = TournamentStatus::Registration;
+        tournament.round = 0;
+        tournament.players = Vec::new();
+        tournament.exit_rounds = Vec::new();
+        tournament.bracket = Vec::new();
+        tournament.winners = Vec::new();
+        tournament.payout_bps = payout_bps.clone();
+        tournament.placements = Vec::new();
+        tournament.created_at = Clock::get()?.unix_timestamp;
+        tournament.closed_at = 0;
+        tournament.bump = ctx.bumps.tournament;
+
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.mint = mint;
+        treasury.bump = ctx.bumps.treasury;
+
+        emit!(TournamentCreated {
+            tournament_id,
+            authority: tournament.authority,
+            mint,
+            buy_in,
+            max_players,
+            payout_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Registers the player, moving the buy-in from their balance into the
+    /// prize pool. Signed like `join_match` and takes the same consent.
+    pub fn register_tournament(
+        ctx: Context<RegisterTournament>,
+        tournament_id: String,
+        max_buy_in: u64,
+        nonce: u64,
+    ) -> Result<()> {
+        let player = ctx.accounts.player.key();
+        let tournament = &mut ctx.accounts.tournament;
+        authorize_signer(
+            ctx.accounts.signer.key(),
+            player,
+            ctx.accounts.session_key.as_deref_mut(),
+            tournament.mint,
+            tournament.buy_in,
+        )?;
+        require!(
+            tournament.status == TournamentStatus::Registration,
+            CustomError::RegistrationClosed
+        );
+        require!(
+            !tournament.players.contains(&player),
+            CustomError::PlayerAlreadyJoined
+        );
+        require!(
+            tournament.players.len() < tournament.max_players as usize,
+            CustomError::MatchFull
+        );
+
+        let buy_in = tournament.buy_in;
+        charge_entry_fee(&mut ctx.accounts.player_balance, buy_in, max_buy_in, nonce)?;
+
+        tournament.prize_pool = tournament
+            .prize_pool
+            .checked_add(buy_in)
+            .ok_or(CustomError::MathOverflow)?;
+        tournament.players.push(player);
+        tournament.exit_rounds.push(0);
+
+        emit!(TournamentRegistered {
+            tournament_id,
+            player,
+            buy_in,
+            nonce,
+        });
+
+        Ok(())
+    }
+
+    /// Closes registration and seeds the bracket in registration order. The
+    /// bracket is padded to the next power of two; players without an
+    /// opponent get a bye and advance straight to the next round.
+    pub fn start_tournament(ctx: Context<ManageTournament>, tournament_id: String) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        require!(
+            tournament.status == TournamentStatus::Registration,
+            CustomError::RegistrationClosed
+        );
+        require!(
+            tournament.players.len() >= 2,
+            CustomError::NotEnoughPlayers
+        );
+
+        // seat i meets seat i + seats/2 of the registration order, so the
+        // byes all go to the first registered players
+        let seats = tournament.players.len().next_power_of_two();
+        let half = seats / 2;
+        let mut bracket = Vec::with_capacity(seats);
+        let mut winners = Vec::with_capacity(half);
+        for i in 0..half {
+            let home = tournament.players[i];
+            let away = tournament
+                .players
+                .get(half + i)
+                .copied()
+                .unwrap_or_default();
+            bracket.push(home);
+            bracket.push(away);
+            winners.push(if away == Pubkey::default() {
+                home
+            } else {
+                Pubkey::default()
+            });
+        }
+        tournament.bracket = bracket;
+        tournament.winners = winners;
+        tournament.status = TournamentStatus::Running;
+
+        emit!(BracketGenerated {
+            tournament_id,
+            round: 0,
+            bracket: tournament.bracket.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Reports the winner of one bracket match of the current round. Once the
+    /// last match of a round is in, the winners advance; once the final is
+    /// in, the placements are fixed and the tournament can be paid out.
+    pub fn report_tournament_match(
+        ctx: Context<ManageTournament>,
+        tournament_id: String,
+        round: u8,
+        match_index: u8,
+        winner: Pubkey,
+    ) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        require!(
+            tournament.status == TournamentStatus::Running,
+            CustomError::TournamentNotRunning
+        );
+        require!(round == tournament.round, CustomError::WrongRound);
+        let index = match_index as usize;
+        require!(
+            index < tournament.winners.len(),
+            CustomError::InvalidBracketMatch
+        );
+        require!(
+            tournament.winners[index] == Pubkey::default(),
+            CustomError::BracketMatchReported
+        );
+
+        let (home, away) = (tournament.bracket[2 * index], tournament.bracket[2 * index + 1]);
+        let loser = if winner == home {
+            away
+        } else if winner == away {
+            home
+        } else {
+            return err!(CustomError::WinnerNotInMatch);
+        };
+        tournament.winners[index] = winner;
+        // exit rounds are stored 1-based, 0 means still in the tournament
+        let loser_index = tournament
+            .players
+            .iter()
+            .position(|p| *p == loser)
+            .ok_or(CustomError::WinnerNotInMatch)?;
+        tournament.exit_rounds[loser_index] = round + 1;
+
+        emit!(TournamentMatchReported {
+            tournament_id: tournament_id.clone(),
+            round,
+            match_index,
+            winner,
+            loser,
+        });
+
+        if tournament.winners.contains(&Pubkey::default()) {
+            return Ok(());
+        }
+        if tournament.winners.len() == 1 {
+            tournament.placements = bracket_placements(tournament, winner);
+            tournament.status = TournamentStatus::Finished;
+
+            emit!(TournamentFinished {
+                tournament_id,
+                champion: winner,
+                placements: tournament.placements.clone(),
+            });
+        } else {
+            tournament.bracket = std::mem::take(&mut tournament.winners);
+            tournament.winners = vec![Pubkey::default(); tournament.bracket.len() / 2];
+            tournament.round += 1;
+
+            emit!(BracketGenerated {
+                tournament_id,
+                round: tournament.round,
+                bracket: tournament.bracket.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Pays the prize pool of a finished tournament to the placements covered
+    /// by its payout table, minus the rake. Permissionless; the balance
+    /// accounts of the paid placements are passed as remaining accounts.
+    pub fn payout_tournament<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PayoutTournament<'info>>,
+        tournament_id: String,
+    ) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        require!(
+            tournament.status != TournamentStatus::Settled,
+            CustomError::MatchAlreadySettled
+        );
+        require!(
+            tournament.status == TournamentStatus::Finished,
+            CustomError::TournamentNotFinished
+        );
+
+        let prize_pool = tournament.prize_pool;
+        let dust_policy = ctx.accounts.config.dust_policy;
+        let (placements, split) = pay_standings(
+            prize_pool,
+            tournament.fee_bps,
+            &mut ctx.accounts.treasury,
+            dust_policy,
+            &tournament.placements,
+            &tournament.payout_bps,
+            ctx.remaining_accounts,
+        )?;
+
+        tournament.prize_pool = 0;
+        tournament.status = TournamentStatus::Settled;
+        tournament.closed_at = Clock::get()?.unix_timestamp;
+
+        emit!(TournamentSettled {
+            tournament_id,
+            mint: tournament.mint,
+            total_pool: prize_pool,
+            rake: split.rake,
+            jackpot_paid: split.jackpot_paid,
+            dust: split.dust,
+            dust_policy,
+            placements,
+            payouts: split.payouts,
+        });
+
+        Ok(())
+    }
+
+    /// Refunds every registered player before the bracket has started
+    /// (remaining accounts in registration order)
+    pub fn cancel_tournament<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ManageTournament<'info>>,
+        tournament_id: String,
+    ) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        require!(
+            tournament.status == TournamentStatus::Registration,
+            CustomError::RegistrationClosed
+        );
+
+        let mut ledger = BalanceLedger::new(tournament.mint);
+        for player_key in &tournament.players {
+            ledger.credit(*player_key, tournament.buy_in)?;
+        }
+        ledger.apply(ctx.remaining_accounts)?;
+
+        let refunded = tournament.prize_pool;
+        tournament.prize_pool = 0;
+        tournament.status = TournamentStatus::Cancelled;
+        tournament.closed_at = Clock::get()?.unix_timestamp;
+
+        emit!(TournamentCancelled {
+            tournament_id,
+            refunded,
+        });
+
+        Ok(())
+    }
+
+    /// Opens a league for up to `max_players` players. Round robin plays
+    /// every pairing once; Swiss plays a fixed number of rounds, pairing
+    /// players with equal scores. The prize pool is paid by final standing.
+    pub fn create_league(
+        ctx: Context<CreateLeague>,
+        league_id: String,
+        mint: Pubkey,
+        buy_in: u64,
+        max_players: u8,
+        format: LeagueFormat,
+        payout_bps: Vec<u16>,
+    ) -> Result<()> {
+        require!(buy_in > 0, CustomError::InvalidAmount);
+        require!(
+            max_players >= 2 && max_players as usize <= MAX_LEAGUE_PLAYERS,
+            CustomError::InvalidLeagueSize
+        );
+        let total_rounds = match format {
+            LeagueFormat::RoundRobin => 0,
+            LeagueFormat::Swiss { rounds } => rounds,
+        };
+        require!(
+            format == LeagueFormat::RoundRobin || total_rounds > 0,
+            CustomError::InvalidRoundCount
+        );
+        validate_payout_table(&payout_bps)?;
+        require!(
+            payout_bps.len() <= max_players as usize,
+            CustomError::InvalidPayoutTable
+        );
+
+        let league = &mut ctx.accounts.league;
+        league.league_id_hash = hash(league_id.as_bytes()).to_bytes();
+        league.authority = ctx.accounts.authority.key();
+        league.mint = mint;
+        league.buy_in = buy_in;
+        league.fee_bps = ctx.accounts.config.fee_bps;
+        league.prize_pool = 0;
+        league.max_players = max_players;
+        league.format = format;
+        league.status = TournamentStatus::Registration;
+        league.round = 0;
+        league.total_rounds = total_rounds;
+        league.players = Vec::new();
+        league.points = Vec::new();
+        league.buchholz = Vec::new();
+        league.games = Vec::new();
+        league.payout_bps = payout_bps.clone();
+        league.placements = Vec::new();
+        league.created_at = Clock::get()?.unix_timestamp;
+        league.closed_at = 0;
+        league.bump = ctx.bumps.league;
+
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.mint = mint;
+        treasury.bump = ctx.bumps.treasury;
+
+        emit!(LeagueCreated {
+            league_id,
+            authority: league.authority,
+            mint,
+            buy_in,
+            max_players,
+            format,
+            payout_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Registers the player, moving the buy-in from their balance into the
+    /// prize pool. Signed like `join_match` and takes the same consent.
+    pub fn register_league(
+        ctx: Context<RegisterLeague>,
+        league_id: String,
+        max_buy_in: u64,
+        nonce: u64,
+    ) -> Result<()> {
+        let player = ctx.accounts.player.key();
+        let league = &mut ctx.accounts.league;
+        authorize_signer(
+            ctx.accounts.signer.key(),
+            player,
+            ctx.accounts.session_key.as_deref_mut(),
+            league.mint,
+            league.buy_in,
+        )?;
+        require!(
+            league.status == TournamentStatus::Registration,
+            CustomError::RegistrationClosed
+        );
+        require!(
+            !league.players.contains(&player),
+            CustomError::PlayerAlreadyJoined
+        );
+        require!(
+            league.players.len() < league.max_players as usize,
+            CustomError::MatchFull
+        );
+
+        let buy_in = league.buy_in;
+        charge_entry_fee(&mut ctx.accounts.player_balance, buy_in, max_buy_in, nonce)?;
+
+        league.prize_pool = league
+            .prize_pool
+            .checked_add(buy_in)
+            .ok_or(CustomError::MathOverflow)?;
+        league.players.push(player);
+        league.points.push(0);
+        league.buchholz.push(0);
+
+        emit!(LeagueRegistered {
+            league_id,
+            player,
+            buy_in,
+            nonce,
+        });
+
+        Ok(())
+    }
+
+    /// Closes registration and pairs the first round
+    pub fn start_league(ctx: Context<ManageLeague>, league_id: String) -> Result<()> {
+        let league = &mut ctx.accounts.league;
+        require!(
+            league.status == TournamentStatus::Registration,
+            CustomError::RegistrationClosed
+        );
+        let players = league.players.len();
+        require!(players >= 2, CustomError::NotEnoughPlayers);
+
+        // a round robin needs one round per opponent, plus one for the byes
+        // with an odd field; Swiss must finish before pairings run out
+        let rounds = players - 1 + players % 2;
+        match league.format {
+            LeagueFormat::RoundRobin => league.total_rounds = rounds as u8,
+            LeagueFormat::Swiss { .. } => require!(
+                league.total_rounds as usize <= rounds,
+                CustomError::InvalidRoundCount
+            ),
+        }
+        league.status = TournamentStatus::Running;
+        pair_league_round(league_id, league)
+    }
+
+    /// Reports the results of the current round, one per game that is not a
+    /// bye, in pairing order. Standings are updated and the next round is
+    /// paired; after the last round the final standings are fixed.
+    pub fn report_league_round(
+        ctx: Context<ManageLeague>,
+        league_id: String,
+        round: u8,
+        results: Vec<GameResult>,
+    ) -> Result<()> {
+        let league: &mut League = &mut ctx.accounts.league;
+        require!(
+            league.status == TournamentStatus::Running,
+            CustomError::TournamentNotRunning
+        );
+        require!(round == league.round, CustomError::WrongRound);
+
+        let mut results = results.into_iter();
+        for game in league.games.iter_mut().filter(|g| g.round == round) {
+            if game.result == GameResult::Bye {
+                continue;
+            }
+            let result = results.next().ok_or(CustomError::ResultCountMismatch)?;
+            let (home, away) = match result {
+                GameResult::HomeWin => (WIN_POINTS, 0),
+                GameResult::AwayWin => (0, WIN_POINTS),
+                GameResult::Draw => (DRAW_POINTS, DRAW_POINTS),
+                GameResult::Pending | GameResult::Bye => {
+                    return err!(CustomError::InvalidGameResult)
+                }
+            };
+            game.result = result;
+            league.points[game.home as usize] += home;
+            league.points[game.away as usize] += away;
+        }
+        require!(
+            results.next().is_none(),
+            CustomError::ResultCountMismatch
+        );
+
+        // Buchholz: the sum of the current points of every opponent met
+        let mut buchholz = vec![0u16; league.players.len()];
+        for game in league.games.iter().filter(|g| g.result != GameResult::Bye) {
+            buchholz[game.home as usize] += league.points[game.away as usize];
+            buchholz[game.away as usize] += league.points[game.home as usize];
+        }
+        league.buchholz = buchholz;
+
+        emit!(LeagueRoundReported {
+            league_id: league_id.clone(),
+            round,
+            points: league.points.clone(),
+            buchholz: league.buchholz.clone(),
+        });
+
+        if round + 1 < league.total_rounds {
+            league.round += 1;
+            return pair_league_round(league_id, league);
+        }
+
+        league.placements = league_standings(league);
+        league.status = TournamentStatus::Finished;
+
+        emit!(LeagueFinished {
+            league_id,
+            placements: league.placements.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Pays the prize pool of a finished league by final standing, like
+    /// `payout_tournament`
+    pub fn payout_league<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PayoutLeague<'info>>,
+        league_id: String,
+    ) -> Result<()> {
+        let league = &mut ctx.accounts.league;
+        require!(
+            league.status != TournamentStatus::Settled,
+            CustomError::MatchAlreadySettled
+        );
+        require!(
+            league.status == TournamentStatus::Finished,
+            CustomError::TournamentNotFinished
+        );
+
+        let prize_pool = league.prize_pool;
+        let dust_policy = ctx.accounts.config.dust_policy;
+        let (placements, split) = pay_standings(
+            prize_pool,
+            league.fee_bps,
+            &mut ctx.accounts.treasury,
+            dust_policy,
+            &league.placements,
+            &league.payout_bps,
+            ctx.remaining_accounts,
+        )?;
+
+        league.prize_pool = 0;
+        league.status = TournamentStatus::Settled;
+        league.closed_at = Clock::get()?.unix_timestamp;
+
+        emit!(LeagueSettled {
+            league_id,
+            mint: league.mint,
+            total_pool: prize_pool,
+            rake: split.rake,
+            jackpot_paid: split.jackpot_paid,
+            dust: split.dust,
+            dust_policy,
+            placements,
+            payouts: split.payouts,
+        });
+
+        Ok(())
+    }
+
+    /// Refunds every registered player before the league has started
+    /// (remaining accounts in registration order)
+    pub fn cancel_league<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ManageLeague<'info>>,
+        league_id: String,
+    ) -> Result<()> {
+        let league = &mut ctx.accounts.league;
+        require!(
+            league.status == TournamentStatus::Registration,
+            CustomError::RegistrationClosed
+        );
+
+        let mut ledger = BalanceLedger::new(league.mint);
+        for player_key in &league.players {
+            ledger.credit(*player_key, league.buy_in)?;
+        }
+        ledger.apply(ctx.remaining_accounts)?;
+
+        let refunded = league.prize_pool;
+        league.prize_pool = 0;
+        league.status = TournamentStatus::Cancelled;
+        league.closed_at = Clock::get()?.unix_timestamp;
+
+        emit!(LeagueCancelled {
+            league_id,
+            refunded,
+        });
+
+        Ok(())
+    }
+}
+
+/// Records a proposed result on the match, starting its dispute window
+fn propose_placements(
+    match_id: String,
+    match_account: &mut Match,
+    proposer: Pubkey,
+    placements: Vec<Placement>,
+    teams: Vec<Team>,
+    payout_bps: Vec<u16>,
+    dispute_window: u64,
+) -> Result<()> {
+    check_proposable(match_account)?;
+    let placements = result_placements(match_account, placements, &teams, &payout_bps)?;
+
+    let mut pending = PendingResult::new(proposer, MatchOutcome::Ranked, dispute_window)?;
+    pending.placements = placements;
+    pending.teams = teams;
+    pending.payout_bps = payout_bps;
+    record_pending(match_id, match_account, pending);
+
+    Ok(())
+}
+
+/// Records a proposed draw, no-contest or void on the match, starting its
+/// dispute window
+fn propose_unranked(
+    match_id: String,
+    match_account: &mut Match,
+    proposer: Pubkey,
+    outcome: MatchOutcome,
+    dispute_window: u64,
+) -> Result<()> {
+    check_proposable(match_account)?;
+    check_outcome(match_account, &outcome)?;
+
+    let pending = PendingResult::new(proposer, outcome, dispute_window)?;
+    record_pending(match_id, match_account, pending);
+
+    Ok(())
+}
+
+/// Checks a draw, no-contest or void against the match: a void has to flag
+/// players of the match, each once
+fn check_outcome(match_account: &Match, outcome: &MatchOutcome) -> Result<()> {
+    match outcome {
+        MatchOutcome::Ranked => return err!(CustomError::InvalidOutcome),
+        MatchOutcome::Draw => require!(
+            !match_account.players.is_empty(),
+            CustomError::NotEnoughPlayers
+        ),
+        MatchOutcome::NoContest => {}
+        MatchOutcome::Void { flagged } => {
+            require!(!flagged.is_empty(), CustomError::InvalidOutcome);
+            for (i, player) in flagged.iter().enumerate() {
+                require!(
+                    match_account.players.contains(player),
+                    CustomError::NotAParticipant
+                );
+                require!(
+                    !flagged[..i].contains(player),
+                    CustomError::DuplicatePlayer
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Proposes a result reported by game servers, ranked or not
+fn propose_match_result(
+    match_account: &mut Match,
+    proposer: Pubkey,
+    result: MatchResult,
+    dispute_window: u64,
+) -> Result<()> {
+    match result.outcome {
+        MatchOutcome::Ranked => propose_placements(
+            result.match_id,
+            match_account,
+            proposer,
+            result.placements,
+            result.teams,
+            result.payout_bps,
+            dispute_window,
+        ),
+        outcome => {
+            require!(
+                result.placements.is_empty() && result.teams.is_empty(),
+                CustomError::InvalidOutcome
+            );
+            propose_unranked(
+                result.match_id,
+                match_account,
+                proposer,
+                outcome,
+                dispute_window,
+            )
+        }
+    }
+}
+
+fn check_proposable(match_account: &Match) -> Result<()> {
+    // The match PDA is seeded by the hashed match id, so a settled
+    // match can never be paid out again no matter how old it is
+    require!(
+        match_account.status != MatchStatus::Settled,
+        CustomError::MatchAlreadySettled
+    );
+    match_account.require_in_play()?;
+    // past the deadline the players are owed a refund, not a result
+    require!(
+        Clock::get()?.unix_timestamp < match_account.deadline,
+        CustomError::MatchDeadlinePassed
+    );
+    Ok(())
+}
+
+fn record_pending(match_id: String, match_account: &mut Match, pending: PendingResult) {
+    emit!(ResultProposed {
+        match_id,
+        proposer: pending.proposer,
+        outcome: pending.outcome.clone(),
+        placements: pending.placements.clone(),
+        teams: pending.teams.clone(),
+        payout_bps: pending.payout_bps.clone(),
+        finalizable_at: pending.finalizable_at,
+    });
+
+    match_account.pending_result = Some(pending);
+    match_account.status = MatchStatus::Proposed;
+}
+
+/// Checks a proposed result and returns its placements, one per player; a
+/// team result places every member at the team's rank. Results that could not
+/// be paid out fail here rather than when they are finalized.
+fn result_placements(
+    match_account: &Match,
+    placements: Vec<Placement>,
+    teams: &[Team],
+    payout_bps: &[u16],
+) -> Result<Vec<Placement>> {
+    let placements = if teams.is_empty() {
+        placements
+    } else {
+        require!(placements.is_empty(), CustomError::InvalidPlacements);
+        require!(teams.len() <= MAX_TEAMS, CustomError::InvalidTeams);
+        for team in teams {
+            require!(
+                !team.members.is_empty() && team.members.len() <= MAX_TEAM_SIZE,
+                CustomError::InvalidTeams
+            );
+            require!(
+                team.weights.is_empty()
+                    || (team.weights.len() == team.members.len()
+                        && team.weights.iter().map(|w| *w as u32).sum::<u32>()
+                            == MAX_BPS as u32),
+                CustomError::InvalidTeamWeights
+            );
+        }
+        teams
+            .iter()
+            .flat_map(|team| {
+                team.members.iter().map(move |member| Placement {
+                    player: *member,
+                    rank: team.rank,
+                })
+            })
+            .collect()
+    };
+    require!(!placements.is_empty(), CustomError::NoWinnersProvided);
+    validate_placements(&match_account.players, &placements)?;
+    result_payouts(match_account.escrow, &placements, teams, payout_bps)?;
+
+    Ok(placements)
+}
+
+/// Payouts of a result in placement order: by placement for a solo result;
+/// by team, then by member weight, for a team result
+fn result_payouts(
+    pool: u64,
+    placements: &[Placement],
+    teams: &[Team],
+    payout_bps: &[u16],
+) -> Result<Vec<u64>> {
+    if teams.is_empty() {
+        let ranks: Vec<u8> = placements.iter().map(|p| p.rank).collect();
+        return placement_payouts(pool, &ranks, payout_bps);
+    }
+
+    let ranks: Vec<u8> = teams.iter().map(|team| team.rank).collect();
+    let team_amounts = placement_payouts(pool, &ranks, payout_bps)?;
+    let mut payouts = Vec::with_capacity(placements.len());
+    for (team, amount) in teams.iter().zip(team_amounts) {
+        for i in 0..team.members.len() {
+            payouts.push(match team.weights.get(i) {
+                Some(weight) => (amount as u128 * *weight as u128 / MAX_BPS as u128) as u64,
+                None => amount / team.members.len() as u64,
+            });
+        }
+    }
+
+    Ok(payouts)
+}
+
+/// Payout table passed with a result, or the stored preset when none is passed
+fn payout_table(payout_bps: Option<Vec<u16>>, preset: Option<&PayoutPreset>) -> Result<Vec<u16>> {
+    match (payout_bps, preset) {
+        (Some(payout_bps), _) => Ok(payout_bps),
+        (None, Some(preset)) => Ok(preset.payout_bps.clone()),
+        (None, None) => err!(CustomError::PayoutTableMissing),
+    }
+}
+
+/// Players holding the top rank of a ranked result, every member of the top
+/// team in a team result, nobody for any other outcome
+fn result_winners(pending: &PendingResult) -> Vec<Pubkey> {
+    match pending.outcome {
+        MatchOutcome::Ranked if pending.teams.is_empty() => {
+            let top = pending.placements.iter().map(|p| p.rank).min();
+            pending
+                .placements
+                .iter()
+                .filter(|p| Some(p.rank) == top)
+                .map(|p| p.player)
+                .collect()
+        }
+        MatchOutcome::Ranked => {
+            let top = pending.teams.iter().map(|team| team.rank).min();
+            pending
+                .teams
+                .iter()
+                .filter(|team| Some(team.rank) == top)
+                .flat_map(|team| team.members.iter().copied())
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Settles a spectator market by the pending result: bets on the winners of a
+/// ranked result share the pool minus the market fee, anything else (a draw,
+/// no-contest or void, or nobody backing a winner) refunds every bet. Bets on
+/// players who left the match are refunded and stay out of the fee.
+fn resolve_market(
+    match_id: String,
+    market: &mut Market,
+    match_account: &Match,
+    treasury: &mut Treasury,
+) -> Result<()> {
+    let pending = match_account
+        .pending_result
+        .as_ref()
+        .ok_or(CustomError::NoPendingResult)?;
+    let winners = result_winners(pending);
+    let refund_pool: u64 = market
+        .pools
+        .iter()
+        .filter(|pool| !match_account.players.contains(&pool.selection))
+        .map(|pool| pool.amount)
+        .sum();
+    let winning_pool: u64 = market
+        .pools
+        .iter()
+        .filter(|pool| winners.contains(&pool.selection))
+        .map(|pool| pool.amount)
+        .sum();
+
+    if winning_pool == 0 {
+        market.status = MarketStatus::Refunding;
+    } else {
+        let pool = market.total - refund_pool;
+        let fee = (pool as u128 * market.fee_bps as u128 / MAX_BPS as u128) as u64;
+        treasury.amount = treasury
+            .amount
+            .checked_add(fee)
+            .ok_or(CustomError::MathOverflow)?;
+        market.fee = fee;
+        market.winning_pool = winning_pool;
+        market.refund_pool = refund_pool;
+        market.status = MarketStatus::Resolved;
+    }
+    market.winners = winners;
+
+    emit!(MarketResolved {
+        match_id,
+        status: market.status,
+        winners: market.winners.clone(),
+        total: market.total,
+        winning_pool: market.winning_pool,
+        fee: market.fee,
+    });
+
+    Ok(())
+}
+
+/// Pays out the pending result, records it in the players' stats and marks
+/// the match as settled
+fn settle_pending<'info>(
+    match_id: String,
+    match_account: &mut Match,
+    treasury: &mut Treasury,
+    dust_policy: DustPolicy,
+    remaining_accounts: &'info [AccountInfo<'info>],
+) -> Result<()> {
+    let pending = match_account
+        .pending_result
+        .take()
+        .ok_or(CustomError::NoPendingResult)?;
+    let winners = result_winners(&pending);
+
+    // a ranked or drawn match is recorded in the stats of every player, whose
+    // stats accounts follow the balance accounts in join order; a no-contest
+    // or void does not count as a played match and takes no stats accounts
+    let counted = matches!(pending.outcome, MatchOutcome::Ranked | MatchOutcome::Draw);
+    let stats_len = if counted {
+        match_account.players.len()
+    } else {
+        0
+    };
+    let stats_start = remaining_accounts
+        .len()
+        .checked_sub(stats_len)
+        .ok_or(CustomError::RemainingAccountsMismatch)?;
+    let (balance_infos, stats_infos) = remaining_accounts.split_at(stats_start);
+
+    // only placement payouts count as winnings: cap refunds and draw shares
+    // hand the players' own stakes back
+    let winnings = match pending.outcome {
+        MatchOutcome::Ranked => settle_placements(
+            match_id,
+            match_account,
+            treasury,
+            dust_policy,
+            pending,
+            balance_infos,
+        )?,
+        MatchOutcome::Draw => {
+            // split pro rata to the stakes (evenly under a fixed entry fee),
+            // remaining_accounts follow the join order
+            let players = match_account.players.clone();
+            let prize_pool = match_account.escrow;
+            let stakes = &match_account.stakes;
+            let split =
+                split_prize_pool(prize_pool, match_account.fee_bps, treasury, dust_policy, |pool| {
+                    Ok(stakes
+                        .iter()
+                        .map(|stake| (pool as u128 * *stake as u128 / prize_pool as u128) as u64)
+                        .collect())
+                })?;
+
+            let mut ledger = BalanceLedger::new(match_account.mint);
+            for (player, payout) in players.iter().zip(&split.payouts) {
+                ledger.credit(*player, *payout)?;
+            }
+            ledger.apply(balance_infos)?;
+
+            emit!(MatchDrawn {
+                match_id,
+                mint: match_account.mint,
+                total_pool: prize_pool,
+                rake: split.rake,
+                jackpot_paid: split.jackpot_paid,
+                dust: split.dust,
+                dust_policy,
+                players,
+                payouts: split.payouts,
+            });
+            Vec::new()
+        }
+        MatchOutcome::NoContest => {
+            // no rake on a refund
+            let ledger = refund_stakes(match_account, |_| true, balance_infos)?;
+
+            emit!(MatchNoContest {
+                match_id,
+                refunded: ledger.total(),
+            });
+            Vec::new()
+        }
+        MatchOutcome::Void { flagged } => {
+            // remaining_accounts skip the flagged players
+            let ledger = refund_stakes(match_account, |p| !flagged.contains(p), balance_infos)?;
+            let refunded = ledger.total();
+            let forfeited = match_account.escrow - refunded;
+            treasury.amount = treasury
+                .amount
+                .checked_add(forfeited)
+                .ok_or(CustomError::MathOverflow)?;
+
+            emit!(MatchVoided {
+                match_id,
+                flagged,
+                refunded,
+                forfeited,
+            });
+            Vec::new()
+        }
+    };
+
+    if counted {
+        record_stats(match_account, &winners, &winnings, stats_infos)?;
+    }
+
+    match_account.escrow = 0;
+    match_account.status = MatchStatus::Settled;
+    match_account.closed_at = Clock::get()?.unix_timestamp;
+
+    Ok(())
+}
+
+/// Pays out a ranked result by placement: takes the rake, splits the rest by
+/// the payout table and routes the dust. Returns each placed player's payout.
+fn settle_placements(
+    match_id: String,
+    match_account: &Match,
+    treasury: &mut Treasury,
+    dust_policy: DustPolicy,
+    pending: PendingResult,
+    balance_infos: &[AccountInfo],
+) -> Result<Vec<(Pubkey, u64)>> {
+    let PendingResult {
+        placements,
+        teams,
+        payout_bps,
+        ..
+    } = pending;
+
+    // winnings are paid out of the escrowed entry fees, minus the house rake
+    let prize_pool = match_account.escrow;
+    let split = split_prize_pool(prize_pool, match_account.fee_bps, treasury, dust_policy, |pool| {
+        result_payouts(pool, &placements, &teams, &payout_bps)
+    })?;
+
+    let mut payouts = split.payouts;
+    let refunds = cap_payouts(match_account, &placements, &mut payouts);
+    let refunded: u64 = refunds.iter().map(|(_, amount)| amount).sum();
+
+    // entry fees were debited on join, so every placed player nets a
+    // single credit; remaining_accounts follow the order of the placements,
+    // then the other refunded players in join order
+    let mut ledger = BalanceLedger::new(match_account.mint);
+    for (placement, payout) in placements.iter().zip(&payouts) {
+        ledger.credit(placement.player, *payout)?;
+    }
+    for (player, refund) in refunds {
+        ledger.credit(player, refund)?;
+    }
+    ledger.apply(balance_infos)?;
+    let winnings = placements
+        .iter()
+        .map(|placement| placement.player)
+        .zip(payouts.iter().copied())
+        .collect();
+
+    emit!(MatchSettled {
+        match_id,
+        mint: match_account.mint,
+        total_pool: prize_pool,
+        rake: split.rake,
+        jackpot_paid: split.jackpot_paid,
+        dust: split.dust,
+        dust_policy,
+        placements,
+        teams,
+        payouts,
+        refunded,
+    });
+
+    Ok(winnings)
+}
+
+/// Adds a settled match to the stats of every player, passed in join order.
+/// A player who never created a stats account is skipped, but the canonical
+/// PDA still has to be passed in their place.
+fn record_stats<'info>(
+    match_account: &Match,
+    winners: &[Pubkey],
+    winnings: &[(Pubkey, u64)],
+    stats_infos: &'info [AccountInfo<'info>],
+) -> Result<()> {
+    require!(
+        stats_infos.len() == match_account.players.len(),
+        CustomError::RemainingAccountsMismatch
+    );
+    let players = match_account.players.iter().zip(&match_account.stakes);
+    for ((player, stake), stats_info) in players.zip(stats_infos) {
+        if stats_info.data_is_empty() {
+            let (address, _) = Pubkey::find_program_address(
+                &[b"stats", player.as_ref(), match_account.mint.as_ref()],
+                &crate::ID,
+            );
+            require_keys_eq!(stats_info.key(), address, CustomError::InvalidStatsAccount);
+            continue;
+        }
+        require!(stats_info.is_writable, CustomError::InvalidStatsAccount);
+        let mut stats = Account::<PlayerStats>::try_from(stats_info)?;
+        require!(
+            stats.player == *player && stats.mint == match_account.mint,
+            CustomError::InvalidStatsAccount
+        );
+        let won = winnings
+            .iter()
+            .filter(|(key, _)| key == player)
+            .map(|(_, amount)| amount)
+            .sum();
+        stats.record(*stake, won, winners.contains(player))?;
+        stats.exit(&crate::ID)?;
+    }
+    Ok(())
+}
+
+/// Caps every payout at `cap_multiple` times the player's own stake, side-pot
+/// style, and returns what the capped players could not take as refunds to
+/// the other players, pro rata to their stakes and in join order
+fn cap_payouts(
+    match_account: &Match,
+    placements: &[Placement],
+    payouts: &mut [u64],
+) -> Vec<(Pubkey, u64)> {
+    let cap_multiple = match match_account.stake_rule {
+        StakeRule::Variable { cap_multiple, .. } if cap_multiple > 0 => cap_multiple as u64,
+        _ => return Vec::new(),
+    };
+
+    let mut capped = Vec::new();
+    let mut leftover = 0u64;
+    for (placement, payout) in placements.iter().zip(payouts.iter_mut()) {
+        let cap = match_account
+            .stake_of(&placement.player)
+            .saturating_mul(cap_multiple);
+        if *payout > cap {
+            leftover += *payout - cap;
+            *payout = cap;
+            capped.push(placement.player);
+        }
+    }
+    if leftover == 0 {
+        return Vec::new();
+    }
+
+    let refunded: Vec<(Pubkey, u64)> = match_account
+        .players
+        .iter()
+        .zip(&match_account.stakes)
+        .filter(|(player, _)| !capped.contains(player))
+        .map(|(player, stake)| (*player, *stake))
+        .collect();
+    // with a jackpot on top, every placed player can hit the cap; the
+    // leftover then stays with the capped players rather than being lost
+    if refunded.is_empty() {
+        for (payout, placement) in payouts.iter_mut().zip(placements) {
+            if capped.contains(&placement.player) {
+                *payout += leftover;
+                break;
+            }
+        }
+        return Vec::new();
+    }
+
+    let total: u64 = refunded.iter().map(|(_, stake)| stake).sum();
+    let mut refunds: Vec<(Pubkey, u64)> = refunded
+        .iter()
+        .map(|(player, stake)| {
+            let share = (leftover as u128 * *stake as u128 / total as u128) as u64;
+            (*player, share)
+        })
+        .collect();
+    // the rounding remainder goes back to the first refunded player
+    let remainder = leftover - refunds.iter().map(|(_, share)| share).sum::<u64>();
+    refunds[0].1 += remainder;
+    refunds
+}
+
+/// Ranks the players of a completed bracket: the champion first, then by the
+/// round they were knocked out in. Players knocked out in the same round share
+/// a rank and keep their registration order.
+fn bracket_placements(tournament: &Tournament, champion: Pubkey) -> Vec<Placement> {
+    let mut order: Vec<(Pubkey, u8)> = tournament
+        .players
+        .iter()
+        .zip(&tournament.exit_rounds)
+        .map(|(player, exit)| {
+            if *player == champion {
+                (*player, u8::MAX)
+            } else {
+                (*player, *exit)
+            }
+        })
+        .collect();
+    order.sort_by_key(|(_, exit)| std::cmp::Reverse(*exit));
+
+    let mut placements: Vec<Placement> = Vec::with_capacity(order.len());
+    for (i, (player, exit)) in order.iter().enumerate() {
+        let rank = match placements.last() {
+            Some(last) if order[i - 1].1 == *exit => last.rank,
+            _ => (i + 1) as u8,
+        };
+        placements.push(Placement {
+            player: *player,
+            rank,
+        });
+    }
+    placements
+}
+
+/// Pairs the current round of a league. Round robin uses the circle method,
+/// so every pairing is played exactly once. Swiss pairs by standing: the
+/// lowest-ranked player without a bye yet sits out an odd round, then each
+/// player from the top meets the highest-ranked player they have not met,
+/// falling back to a rematch only when nobody else is left.
+fn pair_league_round(league_id: String, league: &mut League) -> Result<()> {
+    let round = league.round;
+    let players = league.players.len();
+    let mut pairs: Vec<(u8, u8)> = Vec::with_capacity(players / 2 + 1);
+
+    match league.format {
+        LeagueFormat::RoundRobin => {
+            // pad odd fields with a bye seat; seat 0 stays put and the others
+            // rotate by one position every round
+            let seats = players + players % 2;
+            let rotating = seats - 1;
+            let seat = |i: usize| -> u8 {
+                if i == 0 {
+                    0
+                } else {
+                    (1 + (i - 1 + round as usize) % rotating) as u8
+                }
+            };
+            for i in 0..seats / 2 {
+                let (home, away) = (seat(i), seat(seats - 1 - i));
+                match (home as usize >= players, away as usize >= players) {
+                    (false, false) => pairs.push((home, away)),
+                    (false, true) => pairs.push((home, BYE)),
+                    (true, _) => pairs.push((away, BYE)),
+                }
+            }
+        }
+        LeagueFormat::Swiss { .. } => {
+            let mut order: Vec<u8> = (0..players as u8).collect();
+            order.sort_by_key(|i| std::cmp::Reverse(league.points[*i as usize]));
+
+            if players % 2 == 1 {
+                let had_bye = |i: u8| {
+                    league
+                        .games
+                        .iter()
+                        .any(|g| g.home == i && g.result == GameResult::Bye)
+                };
+                let at = order
+                    .iter()
+                    .rposition(|i| !had_bye(*i))
+                    .unwrap_or(order.len() - 1);
+                pairs.push((order.remove(at), BYE));
+            }
+
+            let met = |a: u8, b: u8| {
+                league
+                    .games
+                    .iter()
+                    .any(|g| (g.home == a && g.away == b) || (g.home == b && g.away == a))
+            };
+            while !order.is_empty() {
+                let home = order.remove(0);
+                let at = order.iter().position(|i| !met(home, *i)).unwrap_or(0);
+                pairs.push((home, order.remove(at)));
+            }
+        }
+    }
+
+    for (home, away) in pairs {
+        let result = if away == BYE {
+            league.points[home as usize] += BYE_POINTS;
+            GameResult::Bye
+        } else {
+            GameResult::Pending
+        };
+        league.games.push(Game {
+            round,
+            home,
+            away,
+            result,
+        });
+    }
+
+    emit!(LeagueRoundPaired {
+        league_id,
+        round,
+        games: league
+            .games
+            .iter()
+            .filter(|g| g.round == round)
+            .cloned()
+            .collect(),
+    });
+
+    Ok(())
+}
+
+/// Ranks the players of a finished league by points, then Buchholz. Players
+/// equal on both share a rank and keep their registration order.
+fn league_standings(league: &League) -> Vec<Placement> {
+    let score = |i: usize| (league.points[i], league.buchholz[i]);
+    let mut order: Vec<usize> = (0..league.players.len()).collect();
+    order.sort_by_key(|i| std::cmp::Reverse(score(*i)));
+
+    let mut placements: Vec<Placement> = Vec::with_capacity(order.len());
+    for (position, i) in order.iter().enumerate() {
+        let rank = match placements.last() {
+            Some(last) if score(order[position - 1]) == score(*i) => last.rank,
+            _ => (position + 1) as u8,
+        };
+        placements.push(Placement {
+            player: league.players[*i],
+            rank,
+        });
+    }
+    placements
+}
+
+/// Pays a prize pool by final standings. Only placements the payout table
+/// reaches are paid; a tied group starting inside the table shares its
+/// positions' basis points. Returns the paid placements and how the pool was
+/// split; `balance_infos` follow the order of the paid placements.
+fn pay_standings(
+    prize_pool: u64,
+    fee_bps: u16,
+    treasury: &mut Treasury,
+    dust_policy: DustPolicy,
+    standings: &[Placement],
+    payout_bps: &[u16],
+    balance_infos: &[AccountInfo],
+) -> Result<(Vec<Placement>, PrizeSplit)> {
+    let placements: Vec<Placement> = standings
+        .iter()
+        .filter(|p| p.rank as usize <= payout_bps.len())
+        .copied()
+        .collect();
+    let ranks: Vec<u8> = placements.iter().map(|p| p.rank).collect();
+    let split = split_prize_pool(prize_pool, fee_bps, treasury, dust_policy, |pool| {
+        placement_payouts(pool, &ranks, payout_bps)
+    })?;
+
+    let mut ledger = BalanceLedger::new(treasury.mint);
+    for (placement, payout) in placements.iter().zip(&split.payouts) {
+        ledger.credit(placement.player, *payout)?;
+    }
+    ledger.apply(balance_infos)?;
+
+    Ok((placements, split))
+}
+
+/// How a prize pool was divided by `split_prize_pool`
+struct PrizeSplit {
+    rake: u64,
+    jackpot_paid: u64,
+    dust: u64,
+    /// Amount owed to each placement, in placement order
+    payouts: Vec<u64>,
+}
+
+/// Takes the rake from `prize_pool`, splits the rest with `payouts_for` and
+/// routes the dust, crediting the treasury's share. The players' credits are
+/// left to the caller.
+fn split_prize_pool(
+    prize_pool: u64,
+    fee_bps: u16,
+    treasury: &mut Treasury,
+    dust_policy: DustPolicy,
+    payouts_for: impl FnOnce(u64) -> Result<Vec<u64>>,
+) -> Result<PrizeSplit> {
+    let rake = (prize_pool as u128 * fee_bps as u128 / MAX_BPS as u128) as u64;
+
+    // under the jackpot policy the dust carried over from earlier matches
+    // is added to this pool's prize money
+    let jackpot_paid = match dust_policy {
+        DustPolicy::Jackpot => std::mem::take(&mut treasury.jackpot),
+        _ => 0,
+    };
+    let distributable = (prize_pool - rake)
+        .checked_add(jackpot_paid)
+        .ok_or(CustomError::MathOverflow)?;
+    let mut payouts = payouts_for(distributable)?;
+
+    // the remainder of the integer splits is routed by the dust policy,
+    // so total_pool + jackpot_paid == rake + payouts + dust to the lamport
+    let dust = distributable - payouts.iter().sum::<u64>();
+    let mut treasury_credit = rake;
+    match dust_policy {
+        DustPolicy::Treasury => treasury_credit += dust,
+        DustPolicy::FirstPlace => payouts[0] += dust,
+        DustPolicy::Jackpot => treasury.jackpot = dust,
+    }
+    treasury.amount = treasury
+        .amount
+        .checked_add(treasury_credit)
+        .ok_or(CustomError::MathOverflow)?;
+
+    Ok(PrizeSplit {
+        rake,
+        jackpot_paid,
+        dust,
+        payouts,
+    })
+}
+
+/// Returns the key that signed `message`, as verified by the Ed25519 precompile
+/// instruction immediately preceding the current one. The precompile has
+/// already checked the signature; here we only make sure it covers exactly
+/// `message` and read the signer out of the instruction data.
+fn ed25519_signer(instructions: &AccountInfo, message: &[u8]) -> Result<Pubkey> {
+    // layout of the precompile data: a 2-byte header followed by one 14-byte
+    // offsets record per signature, all offsets pointing into the same data
+    const HEADER_LEN: usize = 2;
+    const OFFSETS_LEN: usize = 14;
+    const PUBKEY_LEN: usize = 32;
+    const SIGNATURE_LEN: usize = 64;
+
+    let current = load_current_index_checked(instructions)?;
+    require!(current > 0, CustomError::MissingSignature);
+    let ix = load_instruction_at_checked(current as usize - 1, instructions)?;
+    require_keys_eq!(
+        ix.program_id,
+        ed25519_program::ID,
+        CustomError::MissingSignature
+    );
+
+    let data = &ix.data;
+    require!(
+        data.len() >= HEADER_LEN + OFFSETS_LEN && data[0] == 1,
+        CustomError::InvalidSignatureInstruction
+    );
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let offsets = HEADER_LEN;
+    let signature_offset = read_u16(offsets) as usize;
+    let pubkey_offset = read_u16(offsets + 4) as usize;
+    let message_offset = read_u16(offsets + 8) as usize;
+    let message_len = read_u16(offsets + 10) as usize;
+
+    // u16::MAX means "this instruction"; anything else would let the
+    // signature, key or message be pulled from another instruction
+    require!(
+        [offsets + 2, offsets + 6, offsets + 12]
+            .iter()
+            .all(|at| read_u16(*at) == u16::MAX),
+        CustomError::InvalidSignatureInstruction
+    );
+    require!(
+        signature_offset + SIGNATURE_LEN <= data.len()
+            && pubkey_offset + PUBKEY_LEN <= data.len()
+            && message_offset + message_len <= data.len(),
+        CustomError::InvalidSignatureInstruction
+    );
+    require!(
+        data[message_offset..message_offset + message_len] == *message,
+        CustomError::SignedMessageMismatch
+    );
+
+    let pubkey: [u8; PUBKEY_LEN] = data[pubkey_offset..pubkey_offset + PUBKEY_LEN]
+        .try_into()
+        .map_err(|_| CustomError::InvalidSignatureInstruction)?;
+    Ok(Pubkey::new_from_array(pubkey))
+}
+
+/// Checks that `signer` may commit `amount` of the player's balance in `mint`.
+/// The owner always can; anyone else needs a live session key scoped to
+/// joining, whose spend cap still covers the amount.
+fn authorize_signer(
+    signer: Pubkey,
+    player: Pubkey,
+    session: Option<&mut SessionKey>,
+    mint: Pubkey,
+    amount: u64,
+) -> Result<()> {
+    if signer == player {
+        return Ok(());
+    }
+    let session = session.ok_or(CustomError::UnauthorizedSigner)?;
+    require_keys_eq!(session.owner, player, CustomError::UnauthorizedSigner);
+    require_keys_eq!(session.signer, signer, CustomError::UnauthorizedSigner);
+    require!(
+        Clock::get()?.unix_timestamp < session.expires_at,
+        CustomError::SessionExpired
+    );
+    require!(
+        session.scope == SessionScope::JoinMatches,
+        CustomError::SessionScopeViolation
+    );
+    require_keys_eq!(session.mint, mint, CustomError::MintMismatch);
+    let spent = session
+        .spent
+        .checked_add(amount)
+        .ok_or(CustomError::MathOverflow)?;
+    require!(
+        spent <= session.spend_cap,
+        CustomError::SessionSpendCapExceeded
+    );
+    session.spent = spent;
+
+    Ok(())
+}
+
+/// Keeps spectators who bet on a match from joining it and throwing the game:
+/// with a market attached, the joining player's bet PDA must be empty
+fn check_not_bettor(
+    match_account: &Account<Match>,
+    market: Option<&Account<Market>>,
+    bet: Option<&AccountInfo>,
+    player: Pubkey,
+) -> Result<()> {
+    if !match_account.has_market {
+        return Ok(());
+    }
+    let market = market.ok_or(CustomError::MarketMissing)?;
+    let bet = bet.ok_or(CustomError::InvalidBetAccount)?;
+    let (address, _) = Pubkey::find_program_address(
+        &[b"bet", market.key().as_ref(), player.as_ref()],
+        &crate::ID,
+    );
+    require_keys_eq!(bet.key(), address, CustomError::InvalidBetAccount);
+    require!(bet.data_is_empty(), CustomError::BettorCannotJoin);
+    Ok(())
+}
+
+/// Debits `amount` from the player's balance once the player's consent covers
+/// it: `amount` is at most `max_amount` and `nonce` is the next unused nonce
+fn charge_entry_fee(
+    balance: &mut PlayerBalance,
+    amount: u64,
+    max_amount: u64,
+    nonce: u64,
+) -> Result<()> {
+    require!(amount <= max_amount, CustomError::EntryFeeAboveMax);
+    require!(nonce == balance.nonce, CustomError::InvalidNonce);
+    balance.nonce += 1;
+
+    require!(balance.amount >= amount, CustomError::InsufficientBalance);
+    balance.amount -= amount;
+
+    Ok(())
+}
+
+/// Moves the entry fee from the player's balance into the match escrow once the
+/// player's consent (maximum fee and next unused nonce) covers it
+fn escrow_entry_fee(
+    match_id: String,
+    match_account: &mut Match,
+    balance: &mut PlayerBalance,
+    player: Pubkey,
+    max_entry_fee: u64,
+    nonce: u64,
+) -> Result<()> {
+    require!(
+        match_account.status == MatchStatus::Open,
+        CustomError::MatchNotOpen
+    );
+    require!(
+        Clock::get()?.unix_timestamp < match_account.deadline,
+        CustomError::MatchDeadlinePassed
+    );
+    require!(
+        !match_account.players.contains(&player),
+        CustomError::PlayerAlreadyJoined
+    );
+    let capacity = match match_account.seats {
+        0 => MAX_PLAYERS,
+        seats => seats as usize,
+    };
+    require!(
+        match_account.players.len() < capacity,
+        CustomError::MatchFull
+    );
+
+    // the entry fee leaves the player's balance right away, so it can no
+    // longer be withdrawn while the match is in progress
+    let entry_fee = match_account.stake_for(max_entry_fee)?;
+    charge_entry_fee(balance, entry_fee, max_entry_fee, nonce)?;
+
+    match_account.escrow = match_account
+        .escrow
+        .checked_add(entry_fee)
+        .ok_or(CustomError::MathOverflow)?;
+    match_account.players.push(player);
+    match_account.stakes.push(entry_fee);
+
+    emit!(PlayerJoined {
+        match_id: match_id.clone(),
+        player,
+        entry_fee,
+        nonce,
+    });
+
+    // a lobby starts by itself once the last seat is taken
+    if match_account.seats > 0 && match_account.players.len() == capacity {
+        match_account.status = MatchStatus::Started;
+
+        emit!(LobbyStarted {
+            match_id,
+            players: match_account.players.clone(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Makes sure a server-provided result describes exactly this match and is still fresh
+fn check_result(result: &MatchResult, match_account: &Match) -> Result<()> {
+    require!(
+        Clock::get()?.unix_timestamp <= result.expires_at,
+        CustomError::ResultExpired
+    );
+    require!(
+        hash(result.match_id.as_bytes()).to_bytes() == match_account.match_id_hash,
+        CustomError::ResultMismatch
+    );
+    require!(
+        result.entry_fee == match_account.entry_fee,
+        CustomError::ResultMismatch
+    );
+    require!(
+        result.players.len() == match_account.players.len()
+            && result
+                .players
+                .iter()
+                .all(|p| match_account.players.contains(p)),
+        CustomError::ResultMismatch
+    );
+    // with the lengths equal, a repeated key would hide a missing player
+    for (i, player) in result.players.iter().enumerate() {
+        require!(
+            !result.players[..i].contains(player),
+            CustomError::DuplicatePlayer
+        );
+    }
+    Ok(())
+}
+
+/// Checks that every placed player joined the match and is placed only once
+fn validate_placements(players: &[Pubkey], placements: &[Placement]) -> Result<()> {
+    for (i, placement) in placements.iter().enumerate() {
+        require!(
+            players.contains(&placement.player),
+            CustomError::WinnerNotInMatch
+        );
+        require!(
+            !placements[..i].iter().any(|p| p.player == placement.player),
+            CustomError::DuplicatePlayer
+        );
+    }
+    Ok(())
+}
+
+/// Credits back the stake of every player `refund` selects, through one ledger
+/// applied to `balance_infos` in join order
+fn refund_stakes(
+    match_account: &Match,
+    refund: impl Fn(&Pubkey) -> bool,
+    balance_infos: &[AccountInfo],
+) -> Result<BalanceLedger> {
+    let mut ledger = BalanceLedger::new(match_account.mint);
+    for (player, stake) in match_account.players.iter().zip(&match_account.stakes) {
+        if refund(player) {
+            ledger.credit(*player, *stake)?;
+        }
+    }
+    ledger.apply(balance_infos)?;
+    Ok(ledger)
+}
+
+/// Net amount owed to each player by one settlement, in the order players
+/// were first credited. Every balance account is written exactly once.
+struct BalanceLedger {
+    mint: Pubkey,
+    credits: Vec<(Pubkey, u64)>,
+}
+
+impl BalanceLedger {
+    fn new(mint: Pubkey) -> Self {
+        Self {
+            mint,
+            credits: Vec::with_capacity(MAX_PLAYERS),
+        }
+    }
+
+    /// Sum of all credits
+    fn total(&self) -> u64 {
+        self.credits.iter().map(|(_, amount)| amount).sum()
+    }
+
+    fn credit(&mut self, player: Pubkey, amount: u64) -> Result<()> {
+        match self.credits.iter_mut().find(|(key, _)| *key == player) {
+            Some((_, total)) => {
+                *total = total.checked_add(amount).ok_or(CustomError::MathOverflow)?;
+            }
+            None => self.credits.push((player, amount)),
+        }
+        Ok(())
+    }
+
+    /// Applies the ledger to the balance accounts passed through remaining_accounts,
+    /// one account per ledger entry and in the same order
+    fn apply(&self, balance_infos: &[AccountInfo]) -> Result<()> {
+        require!(
+            balance_infos.len() == self.credits.len(),
+            CustomError::RemainingAccountsMismatch
+        );
+        for ((player, amount), balance_info) in self.credits.iter().zip(balance_infos) {
+            credit_player(balance_info, player, &self.mint, *amount)?;
+        }
+        Ok(())
+    }
+}
+
+/// Credits a PlayerBalance in place, patching only the amount field instead of
+/// deserializing and re-serializing the whole account.
+/// The account must be the canonical `[b"player", owner, mint]` PDA.
+fn credit_player(
+    balance_info: &AccountInfo,
+    owner: &Pubkey,
+    mint: &Pubkey,
+    amount: u64,
+) -> Result<()> {
+    require_keys_eq!(
+        *balance_info.owner,
+        crate::ID,
+        CustomError::InvalidPlayerAccount
+    );
+    require!(balance_info.is_writable, CustomError::InvalidPlayerAccount);
+
+    let mut data = balance_info.try_borrow_mut_data()?;
+    require!(
+        data.len() > PlayerBalance::BUMP_OFFSET
+            && data[..8] == *PlayerBalance::DISCRIMINATOR,
+        CustomError::InvalidPlayerAccount
+    );
+    require!(
+        data[PlayerBalance::OWNER_OFFSET..PlayerBalance::MINT_OFFSET] == owner.to_bytes(),
+        CustomError::InvalidPlayerAccount
+    );
+    require!(
+        data[PlayerBalance::MINT_OFFSET..PlayerBalance::AMOUNT_OFFSET] == mint.to_bytes(),
+        CustomError::MintMismatch
+    );
+
+    let bump = data[PlayerBalance::BUMP_OFFSET];
+    let expected = Pubkey::create_program_address(
+        &[b"player", owner.as_ref(), mint.as_ref(), &[bump]],
+        &crate::ID,
+    )
+    .map_err(|_| CustomError::NonCanonicalBalanceAccount)?;
+    require_keys_eq!(
+        balance_info.key(),
+        expected,
+        CustomError::NonCanonicalBalanceAccount
+    );
+
+    if amount == 0 {
+        return Ok(());
+    }
+    let amount_bytes = &mut data[PlayerBalance::AMOUNT_OFFSET..PlayerBalance::BUMP_OFFSET];
+    let balance = u64::from_le_bytes(
+        amount_bytes
+            .try_into()
+            .map_err(|_| CustomError::InvalidPlayerAccount)?,
+    );
+    let balance = balance
+        .checked_add(amount)
+        .ok_or(CustomError::MathOverflow)?;
+    amount_bytes.copy_from_slice(&balance.to_le_bytes());
+    Ok(())
+}
+
+/// A payout table lists the share of each finishing position in basis points
+/// and has to hand out exactly the whole pool
+fn validate_payout_table(payout_bps: &[u16]) -> Result<()> {
+    require!(
+        !payout_bps.is_empty() && payout_bps.len() <= MAX_PLAYERS,
+        CustomError::InvalidPayoutTable
+    );
+    let total: u32 = payout_bps.iter().map(|bps| *bps as u32).sum();
+    require!(total == MAX_BPS as u32, CustomError::InvalidPayoutTable);
+    Ok(())
+}
+
+/// Splits `pool` between placed players (or teams) according to the payout
+/// table, given their ranks in placement order.
+///
+/// Ranks are 1-based and use competition ranking: players tied on rank `r`
+/// occupy positions `r..r + k`, pool the basis points of those positions and
+/// split them evenly, and the next rank after them is `r + k`. Positions past
+/// the end of the table pay nothing. Returns one amount per placement.
+fn placement_payouts(pool: u64, ranks: &[u8], payout_bps: &[u16]) -> Result<Vec<u64>> {
+    validate_payout_table(payout_bps)?;
+
+    let mut payouts = Vec::with_capacity(ranks.len());
+    let mut start = 0;
+    while start < ranks.len() {
+        let rank = ranks[start];
+        // the first player of every rank group sits at position `rank`
+        require!(rank as usize == start + 1, CustomError::InvalidPlacements);
+
+        let end = start + ranks[start..].iter().take_while(|r| **r == rank).count();
+        let group_bps: u64 = payout_bps
+            .iter()
+            .take(end)
+            .skip(start)
+            .map(|bps| *bps as u64)
+            .sum();
+        let group_amount = (pool as u128 * group_bps as u128 / MAX_BPS as u128) as u64;
+        let share = group_amount / (end - start) as u64;
+        payouts.resize(end, share);
+
+        start = end;
+    }
+
+    Ok(payouts)
+}
+
+/// Sends lamports out of the vault, signing the System Program transfer with
+/// the vault PDA seeds. The vault is never taken below its rent reserve.
+fn transfer_from_vault<'info>(
+    vault: &SystemAccount<'info>,
+    destination: AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    vault_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let rent_reserve = Rent::get()?.minimum_balance(0);
+    let available = vault.lamports().saturating_sub(rent_reserve);
+    require!(available >= amount, CustomError::VaultInsufficientFunds);
+
+    let signer_seeds: &[&[&[u8]]] = &[&[b"vault", &[vault_bump]]];
+    system_program::transfer(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: vault.to_account_info(),
+                to: destination,
+            },
+            signer_seeds,
+        ),
+        amount,
+    )
+}
+
+/// Sends tokens out of the mint's vault token account, signed by the vault PDA
+fn transfer_tokens_from_vault<'info>(
+    vault: &SystemAccount<'info>,
+    vault_token_account: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    destination: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    vault_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[b"vault", &[vault_bump]]];
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: vault_token_account.to_account_info(),
+                mint: mint.to_account_info(),
+                to: destination,
+                authority: vault.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    /// Singleton program configuration, can only be created once
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Config::INIT_SPACE,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+
+    /// Has to be the program's upgrade authority
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::SpelltroumTournament>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key()) @ CustomError::NotUpgradeAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut, seeds = [b"config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    /// The wallet of the player, will pay rent if needed
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// Player's balance account, created and initialized on first deposit
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + std::mem::size_of::<PlayerBalance>(),
+        seeds = [b"player", owner.key().as_ref(), SOL_MINT.as_ref()],
+        bump
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+
+    /// Program vault holding the lamports that back every player balance
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub vault: SystemAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"player", owner.key().as_ref(), SOL_MINT.as_ref()],
+        bump,
+        has_one = owner
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub vault: SystemAccount<'info>,
+
+    /// Wallet receiving the lamports, defaults to the owner when omitted
+    #[account(mut)]
+    pub destination: Option<SystemAccount<'info>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositToken<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner,
+        token::token_program = token_program
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Player's balance for this mint, created on first deposit
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + std::mem::size_of::<PlayerBalance>(),
+        seeds = [b"player", owner.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+
+    /// Same vault PDA as for SOL, here only acting as the token authority
+    #[account(seeds = [b"vault"], bump)]
+    pub vault: SystemAccount<'info>,
+
+    /// Program-owned token account holding every deposit of this mint
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = vault,
+        token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawToken<'info> {
+    pub owner: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"player", owner.key().as_ref(), mint.key().as_ref()],
+        bump,
+        has_one = owner,
+        has_one = mint
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+
+    #[account(seeds = [b"vault"], bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = vault,
+        token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token account receiving the withdrawal, any account of the same mint
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String, mint: Pubkey)]
+pub struct CreateMatch<'info> {
+    /// One account per match, seeded by sha256(match_id) so ids of any length fit
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Match::INIT_SPACE,
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Collects the rake for this mint, created with the first match using it
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + std::mem::size_of::<Treasury>(),
+        seeds = [b"treasury", mint.as_ref()],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(
+        mut,
+        constraint = config.is_settlement_authority(&authority.key()) @ CustomError::UnauthorizedAuthority
+    )]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct JoinMatch<'info> {
+    /// The player's wallet or one of their session keys
+    pub signer: Signer<'info>,
+
+    /// CHECK: only used to derive the balance PDA, `signer` is checked against it
+    pub player: UncheckedAccount<'info>,
+
+    /// Required when `signer` is a session key rather than the player
+    #[account(mut)]
+    pub session_key: Option<Account<'info, SessionKey>>,
+
+    #[account(
+        mut,
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    /// Player's balance in the match mint, the entry fee is escrowed from it
+    #[account(
+        mut,
+        seeds = [b"player", player.key().as_ref(), match_account.mint.as_ref()],
+        bump = player_balance.bump,
+        constraint = player_balance.owner == player.key() @ CustomError::InvalidPlayerAccount
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+
+    /// Required when the match has a spectator market
+    #[account(
+        seeds = [b"market", match_account.key().as_ref()],
+        bump = market.bump
+    )]
+    pub market: Option<Account<'info, Market>>,
+
+    /// CHECK: the player's bet PDA in the market, which must not exist;
+    /// required with the market
+    pub bet: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+#[instruction(player: Pubkey, mint: Pubkey)]
+pub struct CreatePlayerStats<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PlayerStats::INIT_SPACE,
+        seeds = [b"stats", player.as_ref(), mint.as_ref()],
+        bump
+    )]
+    pub player_stats: Account<'info, PlayerStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct LeaveMatch<'info> {
+    pub player: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    /// CHECK: credited in place like settlement payouts, `credit_player` checks
+    /// it is the player's canonical balance PDA in the match mint
+    #[account(mut)]
+    pub player_balance: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(session_signer: Pubkey)]
+pub struct CreateSessionKey<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + std::mem::size_of::<SessionKey>(),
+        seeds = [b"session", owner.key().as_ref(), session_signer.as_ref()],
+        bump
+    )]
+    pub session_key: Account<'info, SessionKey>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeSessionKey<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"session", owner.key().as_ref(), session_key.signer.as_ref()],
+        bump = session_key.bump,
+        has_one = owner
+    )]
+    pub session_key: Account<'info, SessionKey>,
+}
+
+#[derive(Accounts)]
+#[instruction(consent: EntryConsent)]
+pub struct JoinMatchWithConsent<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", hash(consent.match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(
+        mut,
+        seeds = [b"player", consent.player.as_ref(), match_account.mint.as_ref()],
+        bump = player_balance.bump,
+        constraint = player_balance.owner == consent.player @ CustomError::InvalidPlayerAccount
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+
+    /// Required when the match has a spectator market
+    #[account(
+        seeds = [b"market", match_account.key().as_ref()],
+        bump = market.bump
+    )]
+    pub market: Option<Account<'info, Market>>,
+
+    /// CHECK: the player's bet PDA in the market, which must not exist;
+    /// required with the market
+    pub bet: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: the instructions sysvar, used to read the Ed25519 precompile instruction
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct ProposeResult<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Stored payout table, used when none is passed with the settlement
+    pub payout_preset: Option<Account<'info, PayoutPreset>>,
+
+    #[account(
+        constraint = config.is_settlement_authority(&authority.key()) @ CustomError::UnauthorizedAuthority
+    )]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(result: MatchResult)]
+pub struct ProposeResultSigned<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", hash(result.match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: the instructions sysvar, used to read the Ed25519 precompile instruction
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(result: MatchResult)]
+pub struct AttestResult<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", hash(result.match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    pub server: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct DisputeResult<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    pub disputer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct ResolveDispute<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = arbiter @ CustomError::UnauthorizedArbiter
+    )]
+    pub config: Account<'info, Config>,
+
+    pub arbiter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct FinalizeResult<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", match_account.mint.as_ref()],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    /// Required when the match has a spectator market
+    #[account(
+        mut,
+        seeds = [b"market", match_account.key().as_ref()],
+        bump = market.bump
+    )]
+    pub market: Option<Account<'info, Market>>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct CancelMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        constraint = config.is_settlement_authority(&authority.key()) @ CustomError::UnauthorizedAuthority
+    )]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct ExpireMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct CreateMarket<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    /// One market per match
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Market::INIT_SPACE,
+        seeds = [b"market", match_account.key().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        constraint = config.is_settlement_authority(&authority.key()) @ CustomError::UnauthorizedAuthority
+    )]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct PlaceBet<'info> {
+    #[account(mut)]
+    pub bettor: Signer<'info>,
+
+    #[account(
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(
+        mut,
+        seeds = [b"market", match_account.key().as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+
+    /// One bet per bettor and market
+    #[account(
+        init_if_needed,
+        payer = bettor,
+        space = 8 + Bet::INIT_SPACE,
+        seeds = [b"bet", market.key().as_ref(), bettor.key().as_ref()],
+        bump
+    )]
+    pub bet: Account<'info, Bet>,
+
+    #[account(
+        mut,
+        seeds = [b"player", bettor.key().as_ref(), match_account.mint.as_ref()],
+        bump = bettor_balance.bump,
+        constraint = bettor_balance.owner == bettor.key() @ CustomError::InvalidPlayerAccount
+    )]
+    pub bettor_balance: Account<'info, PlayerBalance>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: String)]
+pub struct ClaimBet<'info> {
+    /// CHECK: only receives the payout and the bet's rent, the bet and balance
+    /// PDAs are derived from it
+    #[account(mut)]
+    pub bettor: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"match", hash(match_id.as_bytes()).as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, Match>,
+
+    #[account(
+        mut,
+        seeds = [b"market", match_account.key().as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        close = bettor,
+        seeds = [b"bet", market.key().as_ref(), bettor.key().as_ref()],
+        bump = bet.bump
+    )]
+    pub bet: Account<'info, Bet>,
+
+    #[account(
+        mut,
+        seeds = [b"player", bettor.key().as_ref(), match_account.mint.as_ref()],
+        bump = bettor_balance.bump,
+        constraint = bettor_balance.owner == bettor.key() @ CustomError::InvalidPlayerAccount
+    )]
+    pub bettor_balance: Account<'info, PlayerBalance>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", match_account.mint.as_ref()],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: String, mint: Pubkey)]
+pub struct CreateTournament<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Tournament::INIT_SPACE,
+        seeds = [b"tournament", hash(tournament_id.as_bytes()).as_ref()],
+        bump
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + std::mem::size_of::<Treasury>(),
+        seeds = [b"treasury", mint.as_ref()],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(
+        mut,
+        constraint = config.is_settlement_authority(&authority.key()) @ CustomError::UnauthorizedAuthority
+    )]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: String)]
+pub struct RegisterTournament<'info> {
+    /// The player's wallet or one of their session keys
+    pub signer: Signer<'info>,
+
+    /// CHECK: only used to derive the balance PDA, `signer` is checked against it
+    pub player: UncheckedAccount<'info>,
+
+    /// Required when `signer` is a session key rather than the player
+    #[account(mut)]
+    pub session_key: Option<Account<'info, SessionKey>>,
+
+    #[account(
+        mut,
+        seeds = [b"tournament", hash(tournament_id.as_bytes()).as_ref()],
+        bump = tournament.bump
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    #[account(
+        mut,
+        seeds = [b"player", player.key().as_ref(), tournament.mint.as_ref()],
+        bump = player_balance.bump,
+        constraint = player_balance.owner == player.key() @ CustomError::InvalidPlayerAccount
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: String)]
+pub struct ManageTournament<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", hash(tournament_id.as_bytes()).as_ref()],
+        bump = tournament.bump
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        constraint = config.is_settlement_authority(&authority.key()) @ CustomError::UnauthorizedAuthority
+    )]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: String)]
+pub struct PayoutTournament<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", hash(tournament_id.as_bytes()).as_ref()],
+        bump = tournament.bump
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", tournament.mint.as_ref()],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+#[derive(Accounts)]
+#[instruction(league_id: String, mint: Pubkey)]
+pub struct CreateLeague<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + League::INIT_SPACE,
+        seeds = [b"league", hash(league_id.as_bytes()).as_ref()],
+        bump
+    )]
+    pub league: Account<'info, League>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + std::mem::size_of::<Treasury>(),
+        seeds = [b"treasury", mint.as_ref()],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(
+        mut,
+        constraint = config.is_settlement_authority(&authority.key()) @ CustomError::UnauthorizedAuthority
+    )]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(league_id: String)]
+pub struct RegisterLeague<'info> {
+    /// The player's wallet or one of their session keys
+    pub signer: Signer<'info>,
+
+    /// CHECK: only used to derive the balance PDA, `signer` is checked against it
+    pub player: UncheckedAccount<'info>,
+
+    /// Required when `signer` is a session key rather than the player
+    #[account(mut)]
+    pub session_key: Option<Account<'info, SessionKey>>,
+
+    #[account(
+        mut,
+        seeds = [b"league", hash(league_id.as_bytes()).as_ref()],
+        bump = league.bump
+    )]
+    pub league: Account<'info, League>,
+
+    #[account(
+        mut,
+        seeds = [b"player", player.key().as_ref(), league.mint.as_ref()],
+        bump = player_balance.bump,
+        constraint = player_balance.owner == player.key() @ CustomError::InvalidPlayerAccount
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+}
+
+#[derive(Accounts)]
+#[instruction(league_id: String)]
+pub struct ManageLeague<'info> {
+    #[account(
+        mut,
+        seeds = [b"league", hash(league_id.as_bytes()).as_ref()],
+        bump = league.bump
+    )]
+    pub league: Account<'info, League>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        constraint = config.is_settlement_authority(&authority.key()) @ CustomError::UnauthorizedAuthority
+    )]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(league_id: String)]
+pub struct PayoutLeague<'info> {
+    #[account(
+        mut,
+        seeds = [b"league", hash(league_id.as_bytes()).as_ref()],
+        bump = league.bump
+    )]
+    pub league: Account<'info, League>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", league.mint.as_ref()],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+#[derive(Accounts)]
+#[instruction(preset_id: u16)]
+pub struct SetPayoutPreset<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + PayoutPreset::INIT_SPACE,
+        seeds = [b"payout", preset_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub payout_preset: Account<'info, PayoutPreset>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = fee_authority)]
+    pub config: Account<'info, Config>,
+    pub fee_authority: Signer<'info>,
+
+    #[account(mut, seeds = [b"treasury", SOL_MINT.as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub destination: SystemAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasuryToken<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = fee_authority)]
+    pub config: Account<'info, Config>,
+    pub fee_authority: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, seeds = [b"treasury", mint.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(seeds = [b"vault"], bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = vault,
+        token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Config {
+    /// Layout version, bumped whenever the account gains new fields
+    pub version: u8,
+    pub admin: Pubkey,
+    /// Keys allowed to create, settle and cancel matches
+    #[max_len(MAX_AUTHORITIES)]
+    pub settlement_authorities: Vec<Pubkey>,
+    /// Keys whose signed match results can be submitted by anyone
+    #[max_len(MAX_GAME_SERVERS)]
+    pub game_servers: Vec<Pubkey>,
+    /// Matching attestations needed to pay out a quorum-mode match (M of N)
+    pub quorum: u8,
+    /// Platform fee taken from prize pools, in basis points
+    pub fee_bps: u16,
+    /// Key allowed to collect platform fees
+    pub fee_authority: Pubkey,
+    /// Where the remainder of integer prize splits goes
+    pub dust_policy: DustPolicy,
+    /// Slots a proposed result can be disputed before it is paid out
+    pub dispute_window: u64,
+    /// Key that resolves disputed results
+    pub arbiter: Pubkey,
+    /// Seconds after the match deadline a dispute can stay unresolved before
+    /// the match can be expired and refunded
+    pub arbiter_window: i64,
+    /// Fee taken from spectator betting pools, in basis points
+    pub market_fee_bps: u16,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum DustPolicy {
+    /// Credited to the treasury together with the rake
+    Treasury,
+    /// Added to the payout of the first listed placement
+    FirstPlace,
+    /// Kept on the treasury account and added to the next match of the same mint
+    Jackpot,
+}
+
+impl Config {
+    pub fn is_settlement_authority(&self, key: &Pubkey) -> bool {
+        self.settlement_authorities.contains(key)
+    }
+}
+
+/// Reusable payout table for a tournament mode, e.g. 50/30/20 for the top three
+#[account]
+#[derive(InitSpace)]
+pub struct PayoutPreset {
+    pub preset_id: u16,
+    /// Share of each finishing position in basis points, sums to 10000
+    #[max_len(MAX_PLAYERS)]
+    pub payout_bps: Vec<u16>,
+    pub bump: u8,
+}
+
+/// Delegated key of the mobile client, see `create_session_key`
+#[account]
+pub struct SessionKey {
+    pub owner: Pubkey,
+    /// Key that signs on the owner's behalf
+    pub signer: Pubkey,
+    /// Mint of the balance the session may spend from
+    pub mint: Pubkey,
+    pub expires_at: i64,
+    /// Total entry fees the session may commit
+    pub spend_cap: u64,
+    pub spent: u64,
+    pub scope: SessionScope,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SessionScope {
+    /// Join matches, tournaments and leagues with the owner's balance, nothing else
+    JoinMatches,
+}
+
+/// Platform fees collected for one mint, backed by the same vault as player balances
+#[account]
+pub struct Treasury {
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// Rolling jackpot fed by prize split dust, not withdrawable by the fee authority
+    pub jackpot: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct PlayerBalance {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+    /// Next unused entry consent nonce, bumped on every join
+    pub nonce: u64,
+}
+
+impl PlayerBalance {
+    // byte offsets of the serialized fields, after the 8-byte discriminator
+    const OWNER_OFFSET: usize = 8;
+    const MINT_OFFSET: usize = Self::OWNER_OFFSET + 32;
+    const AMOUNT_OFFSET: usize = Self::MINT_OFFSET + 32;
+    const BUMP_OFFSET: usize = Self::AMOUNT_OFFSET + 8;
+}
+
+/// A player's record in one mint, updated by every settled ranked or drawn
+/// match. Tournament and league payouts are not recorded.
+#[account]
+#[derive(InitSpace)]
+pub struct PlayerStats {
+    pub player: Pubkey,
+    pub mint: Pubkey,
+    pub matches_played: u32,
+    pub wins: u32,
+    /// Sum of the player's stakes
+    pub total_wagered: u64,
+    /// Sum of the player's placement payouts, without refunds or draw shares
+    pub total_won: u64,
+    /// Largest placement payout of a single match
+    pub biggest_win: u64,
+    /// Consecutive wins up to the last settled match
+    pub current_streak: u32,
+    pub best_streak: u32,
+    pub bump: u8,
+}
+
+impl PlayerStats {
+    pub fn record(&mut self, wagered: u64, won: u64, win: bool) -> Result<()> {
+        self.matches_played += 1;
+        self.total_wagered = self
+            .total_wagered
+            .checked_add(wagered)
+            .ok_or(CustomError::MathOverflow)?;
+        self.total_won = self
+            .total_won
+            .checked_add(won)
+            .ok_or(CustomError::MathOverflow)?;
+        self.biggest_win = self.biggest_win.max(won);
+        if win {
+            self.wins += 1;
+            self.current_streak += 1;
+            self.best_streak = self.best_streak.max(self.current_streak);
+        } else {
+            self.current_streak = 0;
+        }
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Match {
+    pub match_id_hash: [u8; 32],
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    /// Fixed entry fee, or the minimum stake of a variable-stake match
+    pub entry_fee: u64,
+    pub stake_rule: StakeRule,
+    /// House rake in basis points, copied from Config when the match is created
+    pub fee_bps: u16,
+    /// Entry fees already taken from the players' balances
+    pub escrow: u64,
+    pub status: MatchStatus,
+    pub settlement_mode: SettlementMode,
+    #[max_len(MAX_PLAYERS)]
+    pub players: Vec<Pubkey>,
+    /// Amount each player staked, in join order
+    #[max_len(MAX_PLAYERS)]
+    pub stakes: Vec<u64>,
+    /// Result hashes attested so far by game servers, quorum mode only
+    #[max_len(MAX_GAME_SERVERS)]
+    pub attestations: Vec<Attestation>,
+    /// Result waiting for its dispute window to pass
+    pub pending_result: Option<PendingResult>,
+    pub created_at: i64,
+    /// After this time the match can only be expired and refunded
+    pub deadline: i64,
+    /// Seats of a sit-and-go lobby, 0 for a match without a fixed seat count
+    pub seats: u8,
+    /// A spectator market is attached and has to be resolved on finalize
+    pub has_market: bool,
+    /// Time the match was settled, cancelled or expired
+    pub closed_at: i64,
+    pub bump: u8,
+}
+
+impl Match {
+    /// A match takes a result while open, a lobby only once it has started
+    pub fn require_in_play(&self) -> Result<()> {
+        match self.status {
+            MatchStatus::Open if self.seats > 0 => err!(CustomError::LobbyNotStarted),
+            MatchStatus::Open | MatchStatus::Started => Ok(()),
+            _ => err!(CustomError::MatchNotOpen),
+        }
+    }
+
+    /// Amount a player joining with consent for `max_entry_fee` stakes
+    pub fn stake_for(&self, max_entry_fee: u64) -> Result<u64> {
+        match self.stake_rule {
+            StakeRule::Fixed { entry_fee } => Ok(entry_fee),
+            StakeRule::Variable { min, max, .. } => {
+                require!(
+                    (min..=max).contains(&max_entry_fee),
+                    CustomError::StakeOutOfRange
+                );
+                Ok(max_entry_fee)
+            }
+        }
+    }
+
+    /// Amount `player` staked, 0 if they did not join
+    pub fn stake_of(&self, player: &Pubkey) -> u64 {
+        self.players
+            .iter()
+            .position(|p| p == player)
+            .map_or(0, |i| self.stakes[i])
+    }
+}
+
+/// Parimutuel spectator market attached to a match, see `create_market`
+#[account]
+#[derive(InitSpace)]
+pub struct Market {
+    pub match_account: Pubkey,
+    pub mint: Pubkey,
+    /// No bets are taken from this time on
+    pub lock_time: i64,
+    /// Market fee in basis points, copied from Config when the market is created
+    pub fee_bps: u16,
+    pub status: MarketStatus,
+    /// Sum of all bets
+    pub total: u64,
+    /// Amount staked on each selection
+    #[max_len(MAX_PLAYERS)]
+    pub pools: Vec<SelectionPool>,
+    /// Winning selections, set when the market resolves
+    #[max_len(MAX_PLAYERS)]
+    pub winners: Vec<Pubkey>,
+    /// Amount staked on the winners
+    pub winning_pool: u64,
+    /// Amount staked on players who left the match, refunded on claim
+    pub refund_pool: u64,
+    /// Fee credited to the treasury on resolution
+    pub fee: u64,
+    /// Claimed so far
+    pub paid_out: u64,
+    /// Bet accounts not claimed yet
+    pub open_bets: u32,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct SelectionPool {
+    pub selection: Pubkey,
+    pub amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum MarketStatus {
+    /// Taking bets until the lock time, then waiting for the match result
+    Open,
+    /// Winning bets can claim their share
+    Resolved,
+    /// Every bet can claim its stake back
+    Refunding,
+}
+
+/// A spectator's stake on one selection of a market
+#[account]
+#[derive(InitSpace)]
+pub struct Bet {
+    pub market: Pubkey,
+    pub bettor: Pubkey,
+    pub selection: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+/// Single-elimination tournament, see `create_tournament`
+#[account]
+#[derive(InitSpace)]
+pub struct Tournament {
+    pub tournament_id_hash: [u8; 32],
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    pub buy_in: u64,
+    /// House rake in basis points, copied from Config when the tournament is created
+    pub fee_bps: u16,
+    /// Buy-ins collected from the registered players
+    pub prize_pool: u64,
+    pub max_players: u8,
+    pub status: TournamentStatus,
+    /// Current bracket round, 0-based
+    pub round: u8,
+    /// Registered players, in registration order
+    #[max_len(MAX_TOURNAMENT_PLAYERS)]
+    pub players: Vec<Pubkey>,
+    /// Round each player was knocked out in, 1-based and parallel to `players`;
+    /// 0 while the player is still in
+    #[max_len(MAX_TOURNAMENT_PLAYERS)]
+    pub exit_rounds: Vec<u8>,
+    /// Seats of the current round, match i is seat 2i against seat 2i + 1;
+    /// the default key is an empty seat (bye)
+    #[max_len(MAX_TOURNAMENT_PLAYERS)]
+    pub bracket: Vec<Pubkey>,
+    /// Winner of each match of the current round, default until reported
+    #[max_len(MAX_TOURNAMENT_PLAYERS / 2)]
+    pub winners: Vec<Pubkey>,
+    #[max_len(MAX_TOURNAMENT_PLAYERS)]
+    pub payout_bps: Vec<u16>,
+    /// Final standings, set once the final has been reported
+    #[max_len(MAX_TOURNAMENT_PLAYERS)]
+    pub placements: Vec<Placement>,
+    pub created_at: i64,
+    /// Time the tournament was paid out or cancelled
+    pub closed_at: i64,
+    pub bump: u8,
+}
+
+/// Round-robin or Swiss league, see `create_league`
+#[account]
+#[derive(InitSpace)]
+pub struct League {
+    pub league_id_hash: [u8; 32],
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    pub buy_in: u64,
+    /// House rake in basis points, copied from Config when the league is created
+    pub fee_bps: u16,
+    /// Buy-ins collected from the registered players
+    pub prize_pool: u64,
+    pub max_players: u8,
+    pub format: LeagueFormat,
+    pub status: TournamentStatus,
+    /// Current round, 0-based
+    pub round: u8,
+    /// Rounds to play, fixed when the league starts for round robin
+    pub total_rounds: u8,
+    /// Registered players, in registration order
+    #[max_len(MAX_LEAGUE_PLAYERS)]
+    pub players: Vec<Pubkey>,
+    /// League points, parallel to `players`
+    #[max_len(MAX_LEAGUE_PLAYERS)]
+    pub points: Vec<u16>,
+    /// Buchholz tiebreak (sum of the opponents' points), parallel to `players`
+    #[max_len(MAX_LEAGUE_PLAYERS)]
+    pub buchholz: Vec<u16>,
+    /// Every pairing so far, the current round's last
+    #[max_len(MAX_LEAGUE_GAMES)]
+    pub games: Vec<Game>,
+    #[max_len(MAX_LEAGUE_PLAYERS)]
+    pub payout_bps: Vec<u16>,
+    /// Final standings, set once the last round has been reported
+    #[max_len(MAX_LEAGUE_PLAYERS)]
+    pub placements: Vec<Placement>,
+    pub created_at: i64,
+    /// Time the league was paid out or cancelled
+    pub closed_at: i64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum LeagueFormat {
+    RoundRobin,
+    Swiss { rounds: u8 },
+}
+
+/// Marks the missing opponent of a bye in `Game.away`
+pub const BYE: u8 = u8::MAX;
+
+/// One pairing of a league round, players are indexes into `League.players`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct Game {
+    pub round: u8,
+    pub home: u8,
+    /// `BYE` when the home player sits the round out
+    pub away: u8,
+    pub result: GameResult,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum GameResult {
+    Pending,
+    HomeWin,
+    AwayWin,
+    Draw,
+    /// Scored `BYE_POINTS` when the round is paired
+    Bye,
+}
+
+/// Lifecycle of tournaments and leagues
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum TournamentStatus {
+    /// Players can register and buy in
+    Registration,
+    /// Bracket generated, match results are being reported
+    Running,
+    /// Final reported, waiting for `payout_tournament`
+    Finished,
+    Settled,
+    Cancelled,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct Placement {
+    pub player: Pubkey,
+    /// 1-based finishing position, tied players share the same rank
+    pub rank: u8,
+}
+
+/// Members of one team and the team's finishing position. The team's share
+/// of the prize money is split evenly, or by `weights` when they are given.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub struct Team {
+    /// 1-based position of the team, tied teams share the same rank
+    pub rank: u8,
+    #[max_len(MAX_TEAM_SIZE)]
+    pub members: Vec<Pubkey>,
+    /// One weight per member in basis points summing to 10000, empty for an even split
+    #[max_len(MAX_TEAM_SIZE)]
+    pub weights: Vec<u16>,
+}
+
+/// A player's approval to be charged at most `max_entry_fee` for one match.
+/// Signed off-chain as `CONSENT_DOMAIN` followed by the borsh encoding.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct EntryConsent {
+    pub match_id: String,
+    pub player: Pubkey,
+    pub max_entry_fee: u64,
+    /// Has to equal the player's current `PlayerBalance.nonce`
+    pub nonce: u64,
+}
+
+/// Match outcome as signed by a game server. The signed message is
+/// `RESULT_DOMAIN` followed by the borsh encoding of this struct.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MatchResult {
+    pub match_id: String,
+    /// Everyone who played, has to be the same set as joined on-chain
+    pub players: Vec<Pubkey>,
+    pub placements: Vec<Placement>,
+    /// Team results list the teams here and leave `placements` empty
+    pub teams: Vec<Team>,
+    pub payout_bps: Vec<u16>,
+    /// Anything but `Ranked` leaves placements and teams empty
+    pub outcome: MatchOutcome,
+    pub entry_fee: u64,
+    /// Unix timestamp after which the result can no longer be submitted
+    pub expires_at: i64,
+}
+
+/// How much each player puts into a match
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum StakeRule {
+    /// Every player pays the same entry fee
+    Fixed { entry_fee: u64 },
+    /// Every player picks a stake between `min` and `max`. With a non-zero
+    /// `cap_multiple` a player wins at most that many times their own stake,
+    /// the rest is refunded to the other players.
+    Variable { min: u64, max: u64, cap_multiple: u16 },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum SettlementMode {
+    /// Settled by a settlement authority or by a relayed server-signed result
+    Authority,
+    /// Settled once M of N registered game servers attest the same result
+    Quorum,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct Attestation {
+    pub server: Pubkey,
+    /// sha256 of the borsh-encoded MatchResult
+    pub result_hash: [u8; 32],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum MatchStatus {
+    /// Accepting players, entry fees are held in escrow
+    Open,
+    Settled,
+    Cancelled,
+    /// Refunded after the deadline passed without a settlement
+    Expired,
+    /// A result is pending, see `Match.pending_result`
+    Proposed,
+    /// The pending result is frozen until the arbiter resolves the dispute
+    Disputed,
+    /// A lobby whose seats have all been taken, players can no longer leave
+    Started,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct PendingResult {
+    pub proposer: Pubkey,
+    pub outcome: MatchOutcome,
+    /// One per player, members of a team share the team's rank
+    #[max_len(MAX_PLAYERS)]
+    pub placements: Vec<Placement>,
+    /// Empty unless this is a team result
+    #[max_len(MAX_TEAMS)]
+    pub teams: Vec<Team>,
+    #[max_len(MAX_PLAYERS)]
+    pub payout_bps: Vec<u16>,
+    pub proposed_at: u64,
+    /// First slot at which the result can be finalized
+    pub finalizable_at: u64,
+    pub dispute: Option<Dispute>,
+    /// Set once the arbiter has ruled, the result can no longer be disputed
+    pub arbitrated: bool,
+}
+
+impl PendingResult {
+    /// An empty result by `proposer`, open to disputes for `dispute_window` slots
+    pub fn new(proposer: Pubkey, outcome: MatchOutcome, dispute_window: u64) -> Result<Self> {
+        let slot = Clock::get()?.slot;
+        Ok(Self {
+            proposer,
+            outcome,
+            placements: Vec::new(),
+            teams: Vec::new(),
+            payout_bps: Vec::new(),
+            proposed_at: slot,
+            finalizable_at: slot
+                .checked_add(dispute_window)
+                .ok_or(CustomError::MathOverflow)?,
+            dispute: None,
+            arbitrated: false,
+        })
+    }
+}
+
+/// How a match ended
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub enum MatchOutcome {
+    /// Paid by placements or teams
+    Ranked,
+    /// Prize pool split evenly among all players
+    Draw,
+    /// Every entry fee refunded in full, no rake
+    NoContest,
+    /// Cheating: everyone but the flagged players is refunded, the flagged
+    /// players' entry fees go to the treasury
+    Void {
+        #[max_len(MAX_PLAYERS)]
+        flagged: Vec<Pubkey>,
+    },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct Dispute {
+    pub disputer: Pubkey,
+    /// Hash of the evidence kept off-chain by the support team
+    pub evidence_hash: [u8; 32],
+    pub disputed_at: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum DisputeResolution {
+    /// The proposed result stands
+    Uphold,
+    /// The proposed result is replaced by the arbiter's. Placements, teams and
+    /// payout table are only given for a ranked outcome.
+    Overturn {
+        outcome: MatchOutcome,
+        placements: Vec<Placement>,
+        teams: Vec<Team>,
+        payout_bps: Vec<u16>,
+    },
+}
+
+#[event]
+pub struct ConfigInitialized {
+    pub admin: Pubkey,
+    pub fee_bps: u16,
+    pub fee_authority: Pubkey,
+}
+
+#[event]
+pub struct AdminUpdated {
+    pub previous: Pubkey,
+    pub admin: Pubkey,
+}
+
+#[event]
+pub struct SettlementAuthorityAdded {
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct SettlementAuthorityRemoved {
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct GameServerAdded {
+    pub server: Pubkey,
+}
+
+#[event]
+pub struct GameServerRemoved {
+    pub server: Pubkey,
+}
+
+#[event]
+pub struct QuorumUpdated {
+    pub quorum: u8,
+    pub servers: u8,
+}
+
+#[event]
+pub struct FeeUpdated {
+    pub fee_bps: u16,
+    pub fee_authority: Pubkey,
+}
+
+#[event]
+pub struct PayoutPresetUpdated {
+    pub preset_id: u16,
+    pub payout_bps: Vec<u16>,
+}
+
+#[event]
+pub struct DustPolicyUpdated {
+    pub dust_policy: DustPolicy,
+}
+
+#[event]
+pub struct TreasuryWithdrawn {
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub destination: Pubkey,
+}
+
+#[event]
+pub struct Deposited {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct Withdrawn {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub destination: Pubkey,
+}
+
+#[event]
+pub struct MatchCreated {
+    pub match_id: String,
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    /// Fixed entry fee, or the minimum stake of a variable-stake match
+    pub entry_fee: u64,
+    pub stake_rule: StakeRule,
+    pub settlement_mode: SettlementMode,
+    pub deadline: i64,
+    pub seats: u8,
+}
+
+#[event]
+pub struct SessionKeyCreated {
+    pub owner: Pubkey,
+    pub signer: Pubkey,
+    pub mint: Pubkey,
+    pub expires_at: i64,
+    pub spend_cap: u64,
+    pub scope: SessionScope,
+}
+
+#[event]
+pub struct SessionKeyRevoked {
+    pub owner: Pubkey,
+    pub signer: Pubkey,
+}
+
+#[event]
+pub struct PlayerJoined {
+    pub match_id: String,
+    pub player: Pubkey,
+    pub entry_fee: u64,
+    /// Consent nonce consumed by this join
+    pub nonce: u64,
+}
+
+#[event]
+pub struct MatchSettled {
+    pub match_id: String,
+    pub mint: Pubkey,
+    pub total_pool: u64,
+    /// Platform fee credited to the treasury
+    pub rake: u64,
+    /// Rolling jackpot added to the prize money of this match
+    pub jackpot_paid: u64,
+    /// Remainder of the integer splits, routed by `dust_policy`
+    pub dust: u64,
+    pub dust_policy: DustPolicy,
+    pub placements: Vec<Placement>,
+    /// Team composition of a team match, empty otherwise
+    pub teams: Vec<Team>,
+    /// Amount credited to each placed player, in placement order
+    pub payouts: Vec<u64>,
+    /// Winnings above the payout cap, refunded to the uncapped players
+    pub refunded: u64,
+}
+
+#[event]
+pub struct ResultAttested {
+    pub match_id: String,
+    pub server: Pubkey,
+    pub result_hash: [u8; 32],
+    /// Attestations of this result hash so far, including this one
+    pub attestations: u8,
+}
+
+#[event]
+pub struct ResultConflict {
+    pub match_id: String,
+    pub server: Pubkey,
+    pub result_hash: [u8; 32],
+    pub conflicting_server: Pubkey,
+    pub conflicting_hash: [u8; 32],
+}
+
+#[event]
+pub struct QuorumReached {
+    pub match_id: String,
+    pub result_hash: [u8; 32],
+    pub attestations: u8,
+}
+
+#[event]
+pub struct MatchCancelled {
+    pub match_id: String,
+    pub refunded: u64,
+}
+
+#[event]
+pub struct DisputeConfigUpdated {
+    pub dispute_window: u64,
+    pub arbiter: Pubkey,
+    pub arbiter_window: i64,
+}
+
+#[event]
+pub struct ResultProposed {
+    pub match_id: String,
+    pub proposer: Pubkey,
+    pub outcome: MatchOutcome,
+    pub placements: Vec<Placement>,
+    pub teams: Vec<Team>,
+    pub payout_bps: Vec<u16>,
+    pub finalizable_at: u64,
+}
+
+#[event]
+pub struct ResultDisputed {
+    pub match_id: String,
+    pub disputer: Pubkey,
+    pub evidence_hash: [u8; 32],
+    pub slot: u64,
+}
+
+#[event]
+pub struct DisputeResolved {
+    pub match_id: String,
+    pub arbiter: Pubkey,
+    pub overturned: bool,
+    pub outcome: MatchOutcome,
+    pub placements: Vec<Placement>,
+    pub teams: Vec<Team>,
+    pub payout_bps: Vec<u16>,
+}
+
+#[event]
+pub struct TournamentCreated {
+    pub tournament_id: String,
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    pub buy_in: u64,
+    pub max_players: u8,
+    pub payout_bps: Vec<u16>,
+}
+
+#[event]
+pub struct TournamentRegistered {
+    pub tournament_id: String,
+    pub player: Pubkey,
+    pub buy_in: u64,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct BracketGenerated {
+    pub tournament_id: String,
+    pub round: u8,
+    /// Seats of the round, the default key marks a bye
+    pub bracket: Vec<Pubkey>,
+}
+
+#[event]
+pub struct TournamentMatchReported {
+    pub tournament_id: String,
+    pub round: u8,
+    pub match_index: u8,
+    pub winner: Pubkey,
+    pub loser: Pubkey,
+}
+
+#[event]
+pub struct TournamentFinished {
+    pub tournament_id: String,
+    pub champion: Pubkey,
+    pub placements: Vec<Placement>,
+}
+
+#[event]
+pub struct TournamentSettled {
+    pub tournament_id: String,
+    pub mint: Pubkey,
+    pub total_pool: u64,
+    pub rake: u64,
+    pub jackpot_paid: u64,
+    pub dust: u64,
+    pub dust_policy: DustPolicy,
+    /// Paid placements only
+    pub placements: Vec<Placement>,
+    pub payouts: Vec<u64>,
+}
+
+#[event]
+pub struct TournamentCancelled {
+    pub tournament_id: String,
+    pub refunded: u64,
+}
+
+#[event]
+pub struct LeagueCreated {
+    pub league_id: String,
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    pub buy_in: u64,
+    pub max_players: u8,
+    pub format: LeagueFormat,
+    pub payout_bps: Vec<u16>,
+}
+
+#[event]
+pub struct LeagueRegistered {
+    pub league_id: String,
+    pub player: Pubkey,
+    pub buy_in: u64,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct LeagueRoundPaired {
+    pub league_id: String,
+    pub round: u8,
+    pub games: Vec<Game>,
+}
+
+#[event]
+pub struct LeagueRoundReported {
+    pub league_id: String,
+    pub round: u8,
+    /// Standings after the round, parallel to `League.players`
+    pub points: Vec<u16>,
+    pub buchholz: Vec<u16>,
+}
+
+#[event]
+pub struct LeagueFinished {
+    pub league_id: String,
+    pub placements: Vec<Placement>,
+}
+
+#[event]
+pub struct LeagueSettled {
+    pub league_id: String,
+    pub mint: Pubkey,
+    pub total_pool: u64,
+    pub rake: u64,
+    pub jackpot_paid: u64,
+    pub dust: u64,
+    pub dust_policy: DustPolicy,
+    /// Paid placements only
+    pub placements: Vec<Placement>,
+    pub payouts: Vec<u64>,
+}
+
+#[event]
+pub struct LeagueCancelled {
+    pub league_id: String,
+    pub refunded: u64,
+}
+
+#[event]
+pub struct PlayerLeft {
+    pub match_id: String,
+    pub player: Pubkey,
+    pub refunded: u64,
+}
+
+#[event]
+pub struct LobbyStarted {
+    pub match_id: String,
+    pub players: Vec<Pubkey>,
+}
+
+#[event]
+pub struct MatchDrawn {
     pub match_id: String,
+    pub mint: Pubkey,
     pub total_pool: u64,
+    pub rake: u64,
+    pub jackpot_paid: u64,
+    pub dust: u64,
+    pub dust_policy: DustPolicy,
+    pub players: Vec<Pubkey>,
+    /// Amount credited to each player, in join order
+    pub payouts: Vec<u64>,
+}
+
+#[event]
+pub struct MatchNoContest {
+    pub match_id: String,
+    pub refunded: u64,
+}
+
+#[event]
+pub struct MatchVoided {
+    pub match_id: String,
+    pub flagged: Vec<Pubkey>,
+    pub refunded: u64,
+    /// Entry fees of the flagged players, credited to the treasury
+    pub forfeited: u64,
+}
+
+#[event]
+pub struct MatchExpired {
+    pub match_id: String,
+    pub refunded: u64,
+}
+
+#[event]
+pub struct PlayerStatsCreated {
+    pub player: Pubkey,
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct MarketFeeUpdated {
+    pub market_fee_bps: u16,
+}
+
+#[event]
+pub struct MarketCreated {
+    pub match_id: String,
+    pub market: Pubkey,
+    pub lock_time: i64,
+    pub fee_bps: u16,
+}
+
+#[event]
+pub struct BetPlaced {
+    pub match_id: String,
+    pub bettor: Pubkey,
+    pub selection: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct MarketResolved {
+    pub match_id: String,
+    pub status: MarketStatus,
+    pub winners: Vec<Pubkey>,
+    pub total: u64,
+    pub winning_pool: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct BetClaimed {
+    pub match_id: String,
+    pub bettor: Pubkey,
+    pub selection: Pubkey,
+    pub staked: u64,
+    pub payout: u64,
 }
 
 #[error_code]
@@ -226,4 +5122,160 @@ pub enum CustomError {
     TooManyMatches,
     #[msg("No winners provided")]
     NoWinnersProvided,
+    #[msg("Vault does not hold enough lamports")]
+    VaultInsufficientFunds,
+    #[msg("Balances from different mints cannot be mixed")]
+    MintMismatch,
+    #[msg("Match is not open")]
+    MatchNotOpen,
+    #[msg("Player has already joined this match")]
+    PlayerAlreadyJoined,
+    #[msg("Match is full")]
+    MatchFull,
+    #[msg("Fee must not exceed 10000 basis points")]
+    InvalidFeeBps,
+    #[msg("Signer is not a settlement authority")]
+    UnauthorizedAuthority,
+    #[msg("Settlement authority is already registered")]
+    AuthorityAlreadyAdded,
+    #[msg("Settlement authority not found")]
+    AuthorityNotFound,
+    #[msg("Too many settlement authorities")]
+    TooManyAuthorities,
+    #[msg("Payout table must be non-empty and sum to 10000 basis points")]
+    InvalidPayoutTable,
+    #[msg("No payout table or preset provided")]
+    PayoutTableMissing,
+    #[msg("Placements must be ordered by rank starting at 1")]
+    InvalidPlacements,
+    #[msg("Placed player did not join this match")]
+    WinnerNotInMatch,
+    #[msg("Player appears more than once")]
+    DuplicatePlayer,
+    #[msg("Number of remaining accounts does not match the players")]
+    RemainingAccountsMismatch,
+    #[msg("Balance account is not the canonical player PDA")]
+    NonCanonicalBalanceAccount,
+    #[msg("Game server is already registered")]
+    GameServerAlreadyAdded,
+    #[msg("Game server not found")]
+    GameServerNotFound,
+    #[msg("Too many game servers")]
+    TooManyGameServers,
+    #[msg("Expected an Ed25519 signature instruction before this one")]
+    MissingSignature,
+    #[msg("Malformed Ed25519 signature instruction")]
+    InvalidSignatureInstruction,
+    #[msg("Signed message does not match the submitted data")]
+    SignedMessageMismatch,
+    #[msg("Result is not signed by a registered game server")]
+    UnknownGameServer,
+    #[msg("Signed result has expired")]
+    ResultExpired,
+    #[msg("Signed result does not match the match on-chain")]
+    ResultMismatch,
+    #[msg("Match cannot be settled this way in its settlement mode")]
+    WrongSettlementMode,
+    #[msg("Game server has already attested this match")]
+    AlreadyAttested,
+    #[msg("Quorum must be between 1 and the number of game servers")]
+    QuorumUnreachable,
+    #[msg("Entry fee is above the maximum the player consented to")]
+    EntryFeeAboveMax,
+    #[msg("Consent nonce has already been used or is out of order")]
+    InvalidNonce,
+    #[msg("Entry consent is not signed by the player")]
+    InvalidConsent,
+    #[msg("Signer is neither the player nor a session key of the player")]
+    UnauthorizedSigner,
+    #[msg("Session key has expired")]
+    SessionExpired,
+    #[msg("Session key is not allowed to do this")]
+    SessionScopeViolation,
+    #[msg("Session key spend cap exceeded")]
+    SessionSpendCapExceeded,
+    #[msg("Session expiry must be in the future and within the maximum duration")]
+    InvalidSessionExpiry,
+    #[msg("Match deadline must be in the future")]
+    InvalidDeadline,
+    #[msg("Match deadline has passed")]
+    MatchDeadlinePassed,
+    #[msg("Match deadline has not passed yet")]
+    MatchNotExpired,
+    #[msg("Match has no pending result")]
+    NoPendingResult,
+    #[msg("Only players of the match can dispute its result")]
+    NotAParticipant,
+    #[msg("Dispute window has closed")]
+    DisputeWindowClosed,
+    #[msg("Dispute window is still open")]
+    DisputeWindowOpen,
+    #[msg("Result is under dispute")]
+    ResultUnderDispute,
+    #[msg("Result is not disputed")]
+    NotDisputed,
+    #[msg("Result was already ruled on by the arbiter")]
+    AlreadyArbitrated,
+    #[msg("Signer is not the arbiter")]
+    UnauthorizedArbiter,
+    #[msg("Bracket size must be a power of two between 2 and the maximum")]
+    InvalidBracketSize,
+    #[msg("Tournament registration is closed")]
+    RegistrationClosed,
+    #[msg("Not enough players to start the tournament")]
+    NotEnoughPlayers,
+    #[msg("Tournament is not running")]
+    TournamentNotRunning,
+    #[msg("Result is not for the current round")]
+    WrongRound,
+    #[msg("Bracket match does not exist")]
+    InvalidBracketMatch,
+    #[msg("Bracket match was already reported")]
+    BracketMatchReported,
+    #[msg("Tournament is not finished")]
+    TournamentNotFinished,
+    #[msg("League size must be between 2 and the maximum")]
+    InvalidLeagueSize,
+    #[msg("Invalid number of league rounds")]
+    InvalidRoundCount,
+    #[msg("Number of results does not match the games of the round")]
+    ResultCountMismatch,
+    #[msg("Game result must be a win or a draw")]
+    InvalidGameResult,
+    #[msg("Lobby seat count must be 0 or between 2 and the maximum")]
+    InvalidSeatCount,
+    #[msg("Only lobbies can be left")]
+    NotALobby,
+    #[msg("Lobby has not started yet")]
+    LobbyNotStarted,
+    #[msg("Teams must be non-empty and within the size limits")]
+    InvalidTeams,
+    #[msg("Team weights must match the members and sum to 10000")]
+    InvalidTeamWeights,
+    #[msg("Invalid match outcome")]
+    InvalidOutcome,
+    #[msg("Stake limits must be positive and min must not exceed max")]
+    InvalidStakeRule,
+    #[msg("Stake is outside the match limits")]
+    StakeOutOfRange,
+    #[msg("Lock time must be in the future and not after the match deadline")]
+    InvalidLockTime,
+    #[msg("The market no longer takes bets")]
+    BettingClosed,
+    #[msg("A bet can only be topped up on the same selection")]
+    BetSelectionMismatch,
+    #[msg("The match has a market that must be passed")]
+    MarketMissing,
+    #[msg("The market has not been resolved yet")]
+    MarketNotResolved,
+    #[msg("Stats account does not belong to the player")]
+    InvalidStatsAccount,
+    #[msg("Only the program's upgrade authority can initialize the config")]
+    NotUpgradeAuthority,
+    #[msg("Players of the match cannot bet on it")]
+    PlayerCannotBet,
+    #[msg("Bettors on the match cannot join it")]
+    BettorCannotJoin,
+    #[msg("Bet account is not the player's bet PDA in the market")]
+    InvalidBetAccount,
 }
@@ -1,6 +1,6 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program, BN } from "@coral-xyz/anchor";
-import { PublicKey, SystemProgram } from "@solana/web3.js";
+import { PublicKey, SystemProgram, BPF_LOADER_UPGRADEABLE_PROGRAM_ID } from "@solana/web3.js";
 import { SpelltroumTournament } from "../target/types/spelltroum_tournament";
 // Замените путь на ваш IDL-тайп
 
@@ -14,11 +14,41 @@ async function main() {
     // === Вспомогательная функция для получения PDA баланса игрока ===
     function getPlayerPDA(playerPubkey: PublicKey): PublicKey {
         const [pda] = PublicKey.findProgramAddressSync(
-            [Buffer.from("player"), playerPubkey.toBuffer()],
+            [Buffer.from("player"), playerPubkey.toBuffer(), PublicKey.default.toBuffer()],
             program.programId
         );
         return pda;
     }
+    const [vaultPda] = PublicKey.findProgramAddressSync([Buffer.from("vault")], program.programId);
+
+    // -----------------------------
+    // 1.1) Конфиг программы: создаётся один раз, payer становится админом;
+    //      payer должен быть upgrade authority программы
+    // -----------------------------
+    const [configPda] = PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId);
+    if ((await provider.connection.getAccountInfo(configPda)) === null) {
+        const [programData] = PublicKey.findProgramAddressSync(
+            [program.programId.toBuffer()],
+            BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+        );
+        const txInit = await program.methods
+            .initialize(0, payer)
+            .accounts({
+                config: configPda,
+                admin: payer,
+                program: program.programId,
+                programData,
+                systemProgram: SystemProgram.programId,
+            } as any)
+            .rpc();
+        console.log("✅ initialize:", txInit);
+
+        const txAuthority = await program.methods
+            .addSettlementAuthority(payer)
+            .accounts({ config: configPda, admin: payer } as any)
+            .rpc();
+        console.log("✅ addSettlementAuthority:", txAuthority);
+    }
 
     // -----------------------------
     // 2) Вызов deposit(amount)
@@ -30,65 +60,143 @@ async function main() {
         .accounts({
             owner: payer,
             playerBalance: playerPda, // Тут ошибку получаю
+            vault: vaultPda,
             systemProgram: SystemProgram.programId,
         } as any)
         .rpc();
     console.log("✅ deposit:", txDeposit);
 
     // -----------------------------
-    // 3) Вызов withdraw()
+    // 3) Вызов withdraw(amount) — destination опционален
     // -----------------------------
     const txWithdraw = await program.methods
-        .withdraw()
+        .withdraw(new BN(depositAmount))
         .accounts({
             owner: payer,
             playerBalance: playerPda,
-        })
+            vault: vaultPda,
+            destination: null,
+            systemProgram: SystemProgram.programId,
+        } as any)
         .rpc();
     console.log("✅ withdraw:", txWithdraw);
 
     // -----------------------------
-    // 4) Вызов settle_match(matchId, players, winners, entryFee)
+    // 4) Жизненный цикл матча: create_match -> join_match -> propose_result -> finalize_result
     // -----------------------------
     // 4.1) Параметры матча
-    const matchId = "match_123";
-    const players: PublicKey[] = [
-        // замените на реальные pubkey игроков
-        new PublicKey("Player111111111111111111111111111111111"),
-        new PublicKey("Player222222222222222222222222222222222"),
-    ];
-    const winners: PublicKey[] = [
-        // subset из players
-        players[0],
-    ];
+    const matchId = `match_${Date.now()}`;
     const entryFee = 100; // должна соответствовать BN
 
-    // 4.2) Печатная история матча (предполагается, что аккаунт уже создан!)
-    const matchHistoryPubkey = new PublicKey("HistoryAccount1111111111111111111111111111");
-
-    // 4.3) Собираем remainingAccounts: first все игроки (списываем entryFee), потом победители (получают share)
-    const remainingAccounts = [
-        ...players.map((pk) => ({
-            pubkey: getPlayerPDA(pk),
-            isWritable: true as const,
-            isSigner: false as const,
-        })),
-        ...winners.map((pk) => ({
-            pubkey: getPlayerPDA(pk),
-            isWritable: true as const,
-            isSigner: false as const,
-        })),
-    ];
-
-    const txSettle = await program.methods
-        .settleMatch(matchId, players, winners, new BN(entryFee))
+    // 4.2) PDA матча: seeds = ["match", sha256(matchId)]
+    const [matchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("match"), Buffer.from(anchor.utils.sha256.hash(matchId), "hex")],
+        program.programId
+    );
+
+    // 4.3) Казна комиссий для SOL: seeds = ["treasury", mint]
+    const [treasuryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("treasury"), PublicKey.default.toBuffer()],
+        program.programId
+    );
+
+    // 4.4) Снова пополняем баланс, чтобы хватило на взнос
+    await program.methods
+        .deposit(new BN(depositAmount))
+        .accounts({
+            owner: payer,
+            playerBalance: playerPda,
+            vault: vaultPda,
+            systemProgram: SystemProgram.programId,
+        } as any)
+        .rpc();
+
+    const txCreate = await program.methods
+        // дедлайн: через час матч можно вернуть игрокам через expire_match
+        .createMatch(
+            matchId,
+            PublicKey.default,
+            { fixed: { entryFee: new BN(entryFee) } } as any, // одинаковый взнос для всех
+            { authority: {} } as any,
+            new BN(Math.floor(Date.now() / 1000) + 3600),
+            0 // без фиксированного числа мест (не лобби)
+        )
+        .accounts({
+            matchAccount: matchPda,
+            config: configPda,
+            treasury: treasuryPda,
+            authority: payer,
+            systemProgram: SystemProgram.programId,
+        } as any)
+        .rpc();
+    console.log("✅ createMatch:", txCreate);
+
+    // 4.45) Статистика игрока (seeds = ["stats", player, mint]), без неё расчёт матча её пропускает
+    const [statsPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("stats"), payer.toBuffer(), PublicKey.default.toBuffer()],
+        program.programId
+    );
+    if (!(await provider.connection.getAccountInfo(statsPda))) {
+        const txStats = await program.methods
+            .createPlayerStats(payer, PublicKey.default)
+            .accounts({ payer, playerStats: statsPda } as any)
+            .rpc();
+        console.log("✅ createPlayerStats:", txStats);
+    }
+
+    // 4.5) Взнос уходит из баланса игрока в escrow матча
+    //      согласие игрока: максимальный взнос и следующий неиспользованный nonce
+    const { nonce } = await program.account.playerBalance.fetch(playerPda);
+    const txJoin = await program.methods
+        .joinMatch(matchId, new BN(entryFee), nonce)
+        .accounts({
+            signer: payer,
+            player: payer,
+            sessionKey: null,
+            matchAccount: matchPda,
+            playerBalance: playerPda,
+            market: null,
+            bet: null,
+        } as any)
+        .rpc();
+    console.log("✅ joinMatch:", txJoin);
+
+    // 4.6) Места игроков (ничьи делят одно место) и таблица выплат в bps
+    const placements = [{ player: payer, rank: 1 }];
+    const payoutBps = [10000];
+
+    // remainingAccounts: балансы игроков в том же порядке, что и placements,
+    // затем статистика всех игроков матча в порядке входа
+    const remainingAccounts = placements.map(({ player }) => ({
+        pubkey: getPlayerPDA(player),
+        isWritable: true as const,
+        isSigner: false as const,
+    }));
+    remainingAccounts.push({ pubkey: statsPda, isWritable: true, isSigner: false });
+
+    const txPropose = await program.methods
+        .proposeResult(matchId, placements, payoutBps)
+        .accounts({
+            matchAccount: matchPda,
+            config: configPda,
+            payoutPreset: null,
+            authority: payer,
+        } as any)
+        .rpc();
+    console.log("✅ proposeResult:", txPropose);
+
+    // 4.7) Выплата после окна споров (Config.dispute_window слотов)
+    const txFinalize = await program.methods
+        .finalizeResult(matchId)
         .accounts({
-            matchHistory: matchHistoryPubkey,
-            authority: payer, // Тут ошибку получаю
+            matchAccount: matchPda,
+            config: configPda,
+            treasury: treasuryPda,
+            market: null, // у матча нет рынка ставок зрителей
         } as any)
         .remainingAccounts(remainingAccounts)
         .rpc();
-    console.log("✅ settleMatch:", txSettle);
+    console.log("✅ finalizeResult:", txFinalize);
 }
 
 main()
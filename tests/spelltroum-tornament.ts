@@ -1,8 +1,30 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { SpelltroumTournament } from "../target/types/spelltroum_tournament";
-import { PublicKey, SystemProgram, Keypair, SYSVAR_RENT_PUBKEY } from "@solana/web3.js";
+import {
+  PublicKey,
+  SystemProgram,
+  Keypair,
+  LAMPORTS_PER_SOL,
+  SYSVAR_RENT_PUBKEY,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  Ed25519Program,
+  BPF_LOADER_UPGRADEABLE_PROGRAM_ID,
+} from "@solana/web3.js";
+import {
+  ExtensionType,
+  TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  createAssociatedTokenAccount,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createMint,
+  getAccount,
+  getMintLen,
+  mintTo,
+} from "@solana/spl-token";
 import assert from "assert";
+import fs from "fs";
 
 describe("spelltroum_tournament", () => {
   // Настройка провайдера и программы
@@ -13,13 +35,382 @@ describe("spelltroum_tournament", () => {
   const owner = provider.wallet.publicKey;
   let playerBalancePda: PublicKey;
   let playerBalanceBump: number;
+  let vaultPda: PublicKey;
+  const [configPda] = PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId);
+  const [treasuryPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("treasury"), PublicKey.default.toBuffer()],
+    program.programId
+  );
 
   // Находим PDA для аккаунта баланса игрока
   before(async () => {
     [playerBalancePda, playerBalanceBump] = await PublicKey.findProgramAddress(
-      [Buffer.from("player"), owner.toBuffer()],
+      [Buffer.from("player"), owner.toBuffer(), PublicKey.default.toBuffer()],
       program.programId
     );
+    [vaultPda] = PublicKey.findProgramAddressSync([Buffer.from("vault")], program.programId);
+  });
+
+  // PDA матча: seeds = ["match", sha256(match_id)]
+  const getMatchPda = (matchId: string): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("match"), Buffer.from(anchor.utils.sha256.hash(matchId), "hex")],
+      program.programId
+    )[0];
+
+  // PDA рынка ставок зрителей: seeds = ["market", match]
+  const getMarketPda = (matchId: string): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), getMatchPda(matchId).toBuffer()],
+      program.programId
+    )[0];
+
+  // PDA ставки: seeds = ["bet", market, bettor]
+  const getBetPda = (matchId: string, bettor: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("bet"), getMarketPda(matchId).toBuffer(), bettor.toBuffer()],
+      program.programId
+    )[0];
+
+  // PDA баланса произвольного игрока, по умолчанию в SOL
+  const getBalancePda = (player: PublicKey, mint = PublicKey.default): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("player"), player.toBuffer(), mint.toBuffer()],
+      program.programId
+    )[0];
+
+  // PDA статистики игрока: seeds = ["stats", player, mint]
+  const getStatsPda = (player: PublicKey, mint = PublicKey.default): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("stats"), player.toBuffer(), mint.toBuffer()],
+      program.programId
+    )[0];
+
+  // Новый игрок с лампортами на кошельке и депозитом в программе
+  const fundedPlayer = async (deposit: number, player = Keypair.generate()): Promise<Keypair> => {
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: owner,
+          toPubkey: player.publicKey,
+          lamports: LAMPORTS_PER_SOL / 10,
+        })
+      )
+    );
+    await program.methods
+      .deposit(new anchor.BN(deposit))
+      .accounts({
+        owner: player.publicKey,
+        playerBalance: getBalancePda(player.publicKey),
+        vault: vaultPda,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .signers([player])
+      .rpc();
+    return player;
+  };
+
+  const createStats = (player: PublicKey) =>
+    program.methods
+      .createPlayerStats(player, PublicKey.default)
+      .accounts({ payer: owner, playerStats: getStatsPda(player) } as any)
+      .rpc();
+
+  const balanceOf = async (player: PublicKey): Promise<number> =>
+    (await program.account.playerBalance.fetch(getBalancePda(player))).amount.toNumber();
+
+  // Фиксированный взнос числом или StakeRule объектом
+  const createMatch = async (
+    matchId: string,
+    stake: number | object,
+    settlementMode: object = { authority: {} },
+    deadline = Math.floor(Date.now() / 1000) + 3600,
+    seats = 0
+  ) =>
+    program.methods
+      .createMatch(
+        matchId,
+        PublicKey.default,
+        (typeof stake === "number" ? { fixed: { entryFee: new anchor.BN(stake) } } : stake) as any,
+        settlementMode as any,
+        new anchor.BN(deadline),
+        seats
+      )
+      .accounts({
+        matchAccount: getMatchPda(matchId),
+        config: configPda,
+        treasury: treasuryPda,
+        authority: owner,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .rpc();
+
+  const nonceOf = async (player: PublicKey, mint = PublicKey.default): Promise<anchor.BN> =>
+    (await program.account.playerBalance.fetch(getBalancePda(player, mint))).nonce;
+
+  // Если к матчу привязан рынок, вход проверяет, что игрок не ставил на матч
+  const joinMarketAccounts = async (matchId: string, player: PublicKey) => {
+    const match = await program.account.match.fetch(getMatchPda(matchId));
+    return match.hasMarket
+      ? { market: getMarketPda(matchId), bet: getBetPda(matchId, player) }
+      : { market: null, bet: null };
+  };
+
+  // Вход с согласием игрока: максимальный взнос и следующий nonce
+  const joinMatch = async (
+    matchId: string,
+    player: Keypair,
+    maxEntryFee = 1_000_000,
+    mint = PublicKey.default
+  ) =>
+    program.methods
+      .joinMatch(matchId, new anchor.BN(maxEntryFee), await nonceOf(player.publicKey, mint))
+      .accounts({
+        signer: player.publicKey,
+        player: player.publicKey,
+        sessionKey: null,
+        matchAccount: getMatchPda(matchId),
+        playerBalance: getBalancePda(player.publicKey, mint),
+        ...(await joinMarketAccounts(matchId, player.publicKey)),
+      } as any)
+      .signers([player])
+      .rpc();
+
+  const asRemaining = (players: PublicKey[]) =>
+    players.map((pk) => ({ pubkey: getBalancePda(pk), isWritable: true, isSigner: false }));
+
+  type Placement = { player: PublicKey; rank: number };
+
+  const proposeResult = async (
+    matchId: string,
+    placements: Placement[],
+    payoutBps: number[] | null,
+    payoutPreset: PublicKey | null = null
+  ) =>
+    program.methods
+      .proposeResult(matchId, placements, payoutBps)
+      .accounts({
+        matchAccount: getMatchPda(matchId),
+        config: configPda,
+        payoutPreset,
+        authority: owner,
+      } as any)
+      .rpc();
+
+  // Выплата по предложенному результату: балансы в порядке мест, затем
+  // для рейтингового матча или ничьей статистика всех игроков в порядке входа
+  const finalizeResult = async (
+    matchId: string,
+    players: PublicKey[],
+    market: PublicKey | null = null
+  ) => {
+    const match = await program.account.match.fetch(getMatchPda(matchId));
+    const outcome = match.pendingResult?.outcome ?? {};
+    const counted = "ranked" in outcome || "draw" in outcome;
+    const stats = (counted ? match.players : []).map((pk: PublicKey) => ({
+      pubkey: getStatsPda(pk),
+      isWritable: true,
+      isSigner: false,
+    }));
+    return program.methods
+      .finalizeResult(matchId)
+      .accounts({
+        matchAccount: getMatchPda(matchId),
+        config: configPda,
+        treasury: treasuryPda,
+        market,
+      } as any)
+      .remainingAccounts([...asRemaining(players), ...stats])
+      .rpc();
+  };
+
+  // Окно споров в тестах равно нулю, поэтому результат сразу выплачивается
+  const settleMatch = async (
+    matchId: string,
+    placements: Placement[],
+    payoutBps: number[] | null,
+    payoutPreset: PublicKey | null = null
+  ) => {
+    await proposeResult(matchId, placements, payoutBps, payoutPreset);
+    return finalizeResult(matchId, placements.map((p) => p.player));
+  };
+
+  // Рынок ставок зрителей на матч, приём ставок до lockTime
+  const createMarket = (matchId: string, lockTime = Math.floor(Date.now() / 1000) + 600) =>
+    program.methods
+      .createMarket(matchId, new anchor.BN(lockTime))
+      .accounts({
+        matchAccount: getMatchPda(matchId),
+        market: getMarketPda(matchId),
+        config: configPda,
+        authority: owner,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .rpc();
+
+  const betAccounts = (matchId: string, bettor: PublicKey) => ({
+    bettor,
+    matchAccount: getMatchPda(matchId),
+    market: getMarketPda(matchId),
+    bet: getBetPda(matchId, bettor),
+    bettorBalance: getBalancePda(bettor),
+  });
+
+  const placeBet = (matchId: string, bettor: Keypair, selection: PublicKey, amount: number) =>
+    program.methods
+      .placeBet(matchId, selection, new anchor.BN(amount))
+      .accounts({ ...betAccounts(matchId, bettor.publicKey), systemProgram: SystemProgram.programId } as any)
+      .signers([bettor])
+      .rpc();
+
+  // Забрать выплату может кто угодно, деньги всё равно уходят игроку
+  const claimBet = (matchId: string, bettor: Keypair) =>
+    program.methods
+      .claimBet(matchId)
+      .accounts({ ...betAccounts(matchId, bettor.publicKey), treasury: treasuryPda } as any)
+      .rpc();
+
+  // Токен-аккаунт хранилища для минта: seeds = ["vault", mint], подписывает общий vault PDA
+  const getTokenVaultPda = (mint: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync([Buffer.from("vault"), mint.toBuffer()], program.programId)[0];
+
+  const tokenBalanceOf = async (player: PublicKey, mint: PublicKey): Promise<number> =>
+    (await program.account.playerBalance.fetch(getBalancePda(player, mint))).amount.toNumber();
+
+  const tokenAmount = async (account: PublicKey, tokenProgram: PublicKey): Promise<number> =>
+    Number((await getAccount(provider.connection, account, undefined, tokenProgram)).amount);
+
+  // Игрок с SOL-балансом, токен-аккаунтом и `amount` токенов на нём
+  const tokenHolder = async (mint: PublicKey, tokenProgram: PublicKey, amount: number) => {
+    const payer = (provider.wallet as anchor.Wallet).payer;
+    const player = await fundedPlayer(1);
+    const tokenAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mint,
+      player.publicKey,
+      undefined,
+      tokenProgram
+    );
+    await mintTo(provider.connection, payer, mint, tokenAccount, payer, amount, [], undefined, tokenProgram);
+    return { player, tokenAccount };
+  };
+
+  // Депозит токенов: зачисляется столько, сколько дошло до хранилища
+  const depositToken = (
+    player: Keypair,
+    mint: PublicKey,
+    tokenAccount: PublicKey,
+    tokenProgram: PublicKey,
+    amount: number
+  ) =>
+    program.methods
+      .depositToken(new anchor.BN(amount))
+      .accounts({
+        owner: player.publicKey,
+        mint,
+        ownerTokenAccount: tokenAccount,
+        playerBalance: getBalancePda(player.publicKey, mint),
+        vault: vaultPda,
+        vaultTokenAccount: getTokenVaultPda(mint),
+        tokenProgram,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .signers([player])
+      .rpc();
+
+  const withdrawToken = (
+    player: Keypair,
+    mint: PublicKey,
+    destination: PublicKey,
+    tokenProgram: PublicKey,
+    amount: number
+  ) =>
+    program.methods
+      .withdrawToken(new anchor.BN(amount))
+      .accounts({
+        owner: player.publicKey,
+        mint,
+        playerBalance: getBalancePda(player.publicKey, mint),
+        vault: vaultPda,
+        vaultTokenAccount: getTokenVaultPda(mint),
+        destination,
+        tokenProgram,
+      } as any)
+      .signers([player])
+      .rpc();
+
+  it("Initialize should create the config and let the admin register authorities", async () => {
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+    );
+    const initialize = (admin: Keypair | null) =>
+      program.methods
+        .initialize(0, owner)
+        .accounts({
+          config: configPda,
+          admin: admin ? admin.publicKey : owner,
+          program: program.programId,
+          programData,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .signers(admin ? [admin] : [])
+        .rpc();
+
+    // Только upgrade authority программы может создать Config и стать админом
+    const intruder = Keypair.generate();
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        SystemProgram.transfer({ fromPubkey: owner, toPubkey: intruder.publicKey, lamports: LAMPORTS_PER_SOL / 10 })
+      )
+    );
+    await assert.rejects(() => initialize(intruder), /NotUpgradeAuthority/);
+    await initialize(null);
+
+    await program.methods
+      .addSettlementAuthority(owner)
+      .accounts({ config: configPda, admin: owner } as any)
+      .rpc();
+
+    const config = await program.account.config.fetch(configPda);
+    assert.ok(config.admin.equals(owner), "Admin should be the initializer");
+    assert.strictEqual(config.version, 1);
+    assert.ok(config.settlementAuthorities[0].equals(owner), "Owner should be an authority");
+  });
+
+  it("Admin role can be handed over only by the current admin", async () => {
+    const successor = Keypair.generate();
+    const setAdmin = (admin: Keypair | null, newAdmin: PublicKey) =>
+      program.methods
+        .setAdmin(newAdmin)
+        .accounts({ config: configPda, admin: admin ? admin.publicKey : owner } as any)
+        .signers(admin ? [admin] : [])
+        .rpc();
+
+    await assert.rejects(() => setAdmin(successor, successor.publicKey), /ConstraintHasOne|has one/);
+    await setAdmin(null, successor.publicKey);
+    let config = await program.account.config.fetch(configPda);
+    assert.ok(config.admin.equals(successor.publicKey), "Admin should be handed over");
+
+    // Новый админ возвращает роль, старый больше ничего не может
+    await assert.rejects(() => setAdmin(null, owner), /ConstraintHasOne|has one/);
+    await setAdmin(successor, owner);
+    config = await program.account.config.fetch(configPda);
+    assert.ok(config.admin.equals(owner), "Admin should be handed back");
+  });
+
+  it("Only the admin can change settlement authorities", async () => {
+    const intruder = Keypair.generate();
+    await assert.rejects(
+      () =>
+        program.methods
+          .addSettlementAuthority(intruder.publicKey)
+          .accounts({ config: configPda, admin: intruder.publicKey } as any)
+          .signers([intruder])
+          .rpc(),
+      /ConstraintHasOne|has one/
+    );
   });
 
   it("Initial deposit should create player balance account and set amount", async () => {
@@ -31,6 +422,7 @@ describe("spelltroum_tournament", () => {
       .accounts({
         owner,
         playerBalance: playerBalancePda,
+        vault: vaultPda,
         systemProgram: SystemProgram.programId,
       } as any)
       .rpc();
@@ -39,6 +431,12 @@ describe("spelltroum_tournament", () => {
     const account = await program.account.playerBalance.fetch(playerBalancePda);
     assert.ok(account.amount.eq(depositAmount), "Amount should match deposit");
     assert.ok(account.owner.equals(owner), "Owner should be set correctly");
+    assert.ok(account.mint.equals(PublicKey.default), "SOL balances use the default mint key");
+
+    // Хранилище должно держать депозит поверх резерва на ренту
+    const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(0);
+    const vaultLamports = await provider.connection.getBalance(vaultPda);
+    assert.strictEqual(vaultLamports, rentReserve + depositAmount.toNumber());
   });
 
   it("Deposit zero should fail with InvalidAmount", async () => {
@@ -50,6 +448,7 @@ describe("spelltroum_tournament", () => {
           .accounts({
             owner,
             playerBalance: playerBalancePda,
+            vault: vaultPda,
             systemProgram: SystemProgram.programId,
           } as any)
           .rpc(),
@@ -57,6 +456,68 @@ describe("spelltroum_tournament", () => {
     );
   });
 
+  it("Partial withdraw should keep the rest of the balance", async () => {
+    await program.methods
+      .withdraw(new anchor.BN(400))
+      .accounts({
+        owner,
+        playerBalance: playerBalancePda,
+        vault: vaultPda,
+        destination: null,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .rpc();
+
+    const account = await program.account.playerBalance.fetch(playerBalancePda);
+    assert.ok(account.amount.eq(new anchor.BN(600)), "Remaining balance should be kept");
+  });
+
+  it("Withdraw more than the balance should fail with InsufficientBalance", async () => {
+    await assert.rejects(
+      () =>
+        program.methods
+          .withdraw(new anchor.BN(10_000))
+          .accounts({
+            owner,
+            playerBalance: playerBalancePda,
+            vault: vaultPda,
+            destination: null,
+            systemProgram: SystemProgram.programId,
+          } as any)
+          .rpc(),
+      /InsufficientBalance/
+    );
+  });
+
+  it("Withdraw can pay out to another wallet", async () => {
+    // Кошелёк-получатель должен остаться rent-exempt, поэтому суммы крупнее
+    const destination = Keypair.generate().publicKey;
+    await program.methods
+      .deposit(new anchor.BN(LAMPORTS_PER_SOL))
+      .accounts({
+        owner,
+        playerBalance: playerBalancePda,
+        vault: vaultPda,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .rpc();
+
+    await program.methods
+      .withdraw(new anchor.BN(LAMPORTS_PER_SOL / 2))
+      .accounts({
+        owner,
+        playerBalance: playerBalancePda,
+        vault: vaultPda,
+        destination,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .rpc();
+
+    assert.strictEqual(await provider.connection.getBalance(destination), LAMPORTS_PER_SOL / 2);
+    const account = await program.account.playerBalance.fetch(playerBalancePda);
+    assert.ok(account.amount.eq(new anchor.BN(600 + LAMPORTS_PER_SOL / 2)));
+  });
+
   it("Withdraw should set amount to zero", async () => {
     // Сначала чуть-чуть депозита, чтобы было что снимать
     await program.methods
@@ -64,21 +525,32 @@ describe("spelltroum_tournament", () => {
       .accounts({
         owner,
         playerBalance: playerBalancePda,
+        vault: vaultPda,
         systemProgram: SystemProgram.programId,
       } as any)
       .rpc();
 
-    // Вызываем withdraw
+    const vaultBefore = await provider.connection.getBalance(vaultPda);
+    const { amount } = await program.account.playerBalance.fetch(playerBalancePda);
+
+    // Снимаем весь баланс
     await program.methods
-      .withdraw()
+      .withdraw(amount)
       .accounts({
         owner,
         playerBalance: playerBalancePda,
-      })
+        vault: vaultPda,
+        destination: null,
+        systemProgram: SystemProgram.programId,
+      } as any)
       .rpc();
 
     const account = await program.account.playerBalance.fetch(playerBalancePda);
     assert.ok(account.amount.eq(new anchor.BN(0)), "Amount should be reset to zero");
+
+    // Лампорты действительно ушли из хранилища
+    const vaultAfter = await provider.connection.getBalance(vaultPda);
+    assert.strictEqual(vaultBefore - vaultAfter, amount.toNumber());
   });
 
   it("Withdraw with zero balance should fail with NothingToWithdraw", async () => {
@@ -86,15 +558,1686 @@ describe("spelltroum_tournament", () => {
     await assert.rejects(
       () =>
         program.methods
-          .withdraw()
+          .withdraw(new anchor.BN(1))
           .accounts({
             owner,
             playerBalance: playerBalancePda,
-          })
+            vault: vaultPda,
+            destination: null,
+            systemProgram: SystemProgram.programId,
+          } as any)
           .rpc(),
       /NothingToWithdraw/
     );
   });
 
-  // TODO: написать тесты для settle_match после добавления в контракт инициализации MatchHistory
+  it("Match lifecycle: entry fees are escrowed on join and paid out on settle", async () => {
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+
+    // Взнос списан сразу при входе в матч
+    assert.strictEqual(await balanceOf(bob.publicKey), 900);
+    let match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok(match.escrow.eq(new anchor.BN(200)), "Escrow should hold both entry fees");
+
+    const settle = () => settleMatch(matchId, [{ player: alice.publicKey, rank: 1 }], [10000]);
+    await settle();
+
+    assert.strictEqual(await balanceOf(alice.publicKey), 1100);
+    assert.strictEqual(await balanceOf(bob.publicKey), 900);
+    match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("settled" in match.status, "Match should be marked as settled");
+
+    await assert.rejects(settle, /MatchAlreadySettled/);
+  });
+
+  it("Settle by a key outside the allow-list should fail with UnauthorizedAuthority", async () => {
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const intruder = Keypair.generate();
+
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await assert.rejects(
+      () =>
+        program.methods
+          .proposeResult(matchId, [{ player: alice.publicKey, rank: 1 }], [10000])
+          .accounts({
+            matchAccount: getMatchPda(matchId),
+            config: configPda,
+            payoutPreset: null,
+            authority: intruder.publicKey,
+          } as any)
+          .signers([intruder])
+          .rpc(),
+      /UnauthorizedAuthority/
+    );
+  });
+
+  it("Settle should pay placements by the payout table and split ties", async () => {
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    const carol = await fundedPlayer(1000);
+
+    await createMatch(matchId, 100);
+    for (const player of [alice, bob, carol]) {
+      await joinMatch(matchId, player);
+    }
+
+    // 50/30/20, Боб и Кэрол делят второе место: (30% + 20%) / 2 каждому
+    await settleMatch(
+      matchId,
+      [
+        { player: alice.publicKey, rank: 1 },
+        { player: bob.publicKey, rank: 2 },
+        { player: carol.publicKey, rank: 2 },
+      ],
+      [5000, 3000, 2000]
+    );
+
+    assert.strictEqual(await balanceOf(alice.publicKey), 900 + 150);
+    assert.strictEqual(await balanceOf(bob.publicKey), 900 + 75);
+    assert.strictEqual(await balanceOf(carol.publicKey), 900 + 75);
+  });
+
+  it("Settle can use a stored payout preset", async () => {
+    const [presetPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("payout"), new anchor.BN(1).toArrayLike(Buffer, "le", 2)],
+      program.programId
+    );
+    await program.methods
+      .setPayoutPreset(1, [7000, 3000])
+      .accounts({
+        config: configPda,
+        payoutPreset: presetPda,
+        admin: owner,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .rpc();
+
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+
+    await settleMatch(
+      matchId,
+      [
+        { player: bob.publicKey, rank: 1 },
+        { player: alice.publicKey, rank: 2 },
+      ],
+      null,
+      presetPda
+    );
+
+    assert.strictEqual(await balanceOf(bob.publicKey), 900 + 140);
+    assert.strictEqual(await balanceOf(alice.publicKey), 900 + 60);
+  });
+
+  it("Payout table that does not sum to 10000 should fail with InvalidPayoutTable", async () => {
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+
+    await assert.rejects(
+      () => settleMatch(matchId, [{ player: alice.publicKey, rank: 1 }], [5000, 4000]),
+      /InvalidPayoutTable/
+    );
+  });
+
+  it("Split dust should follow the dust policy", async () => {
+    // 3 × 101 = 303 на двоих победителей: по 151 и 1 лампорт остатка
+    const playTiedMatch = async () => {
+      const matchId = `match_${Date.now()}`;
+      const players = [await fundedPlayer(1000), await fundedPlayer(1000), await fundedPlayer(1000)];
+      await createMatch(matchId, 101);
+      for (const player of players) {
+        await joinMatch(matchId, player);
+      }
+      await settleMatch(
+        matchId,
+        [
+          { player: players[0].publicKey, rank: 1 },
+          { player: players[1].publicKey, rank: 1 },
+        ],
+        [10000]
+      );
+      return players;
+    };
+
+    // По умолчанию остаток уходит в казну
+    let treasuryBefore = (await program.account.treasury.fetch(treasuryPda)).amount.toNumber();
+    let players = await playTiedMatch();
+    assert.strictEqual(await balanceOf(players[0].publicKey), 899 + 151);
+    assert.strictEqual(
+      (await program.account.treasury.fetch(treasuryPda)).amount.toNumber() - treasuryBefore,
+      1
+    );
+
+    // FirstPlace: остаток получает первый в списке мест
+    await program.methods
+      .setDustPolicy({ firstPlace: {} })
+      .accounts({ config: configPda, admin: owner } as any)
+      .rpc();
+    treasuryBefore = (await program.account.treasury.fetch(treasuryPda)).amount.toNumber();
+    players = await playTiedMatch();
+    assert.strictEqual(await balanceOf(players[0].publicKey), 899 + 152);
+    assert.strictEqual(await balanceOf(players[1].publicKey), 899 + 151);
+    assert.strictEqual(
+      (await program.account.treasury.fetch(treasuryPda)).amount.toNumber(),
+      treasuryBefore
+    );
+
+    await program.methods
+      .setDustPolicy({ treasury: {} })
+      .accounts({ config: configPda, admin: owner } as any)
+      .rpc();
+  });
+
+  it("Jackpot dust should carry over to the next match and stay out of the fee withdrawal", async () => {
+    await program.methods
+      .setDustPolicy({ jackpot: {} })
+      .accounts({ config: configPda, admin: owner } as any)
+      .rpc();
+
+    // Первый матч: 303 на двоих победителей, 1 лампорт остатка уходит в джекпот
+    const firstId = `match_${Date.now()}`;
+    const first = [await fundedPlayer(1000), await fundedPlayer(1000), await fundedPlayer(1000)];
+    await createMatch(firstId, 101);
+    for (const player of first) {
+      await joinMatch(firstId, player);
+    }
+    const treasuryBefore = (await program.account.treasury.fetch(treasuryPda)).amount.toNumber();
+    await settleMatch(
+      firstId,
+      [
+        { player: first[0].publicKey, rank: 1 },
+        { player: first[1].publicKey, rank: 1 },
+      ],
+      [10000]
+    );
+    assert.strictEqual(await balanceOf(first[0].publicKey), 899 + 151);
+    assert.strictEqual(await balanceOf(first[1].publicKey), 899 + 151);
+    let treasury = await program.account.treasury.fetch(treasuryPda);
+    assert.strictEqual(treasury.amount.toNumber(), treasuryBefore);
+    assert.strictEqual(treasury.jackpot.toNumber(), 1);
+
+    // fee authority выводит всю комиссию, но джекпот ему недоступен
+    const withdrawTreasury = (amount: number) =>
+      program.methods
+        .withdrawTreasury(new anchor.BN(amount))
+        .accounts({
+          config: configPda,
+          feeAuthority: owner,
+          treasury: treasuryPda,
+          vault: vaultPda,
+          destination: owner,
+          systemProgram: SystemProgram.programId,
+        } as any)
+        .rpc();
+    if (treasuryBefore > 0) {
+      await withdrawTreasury(treasuryBefore);
+    }
+    await assert.rejects(() => withdrawTreasury(1), /InsufficientBalance/);
+    treasury = await program.account.treasury.fetch(treasuryPda);
+    assert.strictEqual(treasury.amount.toNumber(), 0);
+    assert.strictEqual(treasury.jackpot.toNumber(), 1);
+
+    // Второй матч: джекпот добавляется к призовому фонду и обнуляется
+    const secondId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createMatch(secondId, 100);
+    await joinMatch(secondId, alice);
+    await joinMatch(secondId, bob);
+    await proposeResult(secondId, [{ player: alice.publicKey, rank: 1 }], [10000]);
+    const signature = await finalizeResult(secondId, [alice.publicKey]);
+    await provider.connection.confirmTransaction(signature, "confirmed");
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const events = [...parser.parseLogs(tx.meta.logMessages)];
+    const settled = events.find((e) => e.name === "matchSettled");
+    assert.ok(settled, "MatchSettled should be emitted");
+    assert.strictEqual(settled.data.jackpotPaid.toNumber(), 1);
+    assert.strictEqual(settled.data.dust.toNumber(), 0);
+
+    assert.strictEqual(await balanceOf(alice.publicKey), 900 + 201);
+    assert.strictEqual(await balanceOf(bob.publicKey), 900);
+    treasury = await program.account.treasury.fetch(treasuryPda);
+    assert.strictEqual(treasury.amount.toNumber(), 0);
+    assert.strictEqual(treasury.jackpot.toNumber(), 0);
+
+    await program.methods
+      .setDustPolicy({ treasury: {} })
+      .accounts({ config: configPda, admin: owner } as any)
+      .rpc();
+  });
+
+  it("Malformed settlements should fail with dedicated errors", async () => {
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    const outsider = await fundedPlayer(1000);
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+
+    // Победитель не участвовал в матче
+    await assert.rejects(
+      () => settleMatch(matchId, [{ player: outsider.publicKey, rank: 1 }], [10000]),
+      /WinnerNotInMatch/
+    );
+
+    // Один и тот же игрок дважды
+    await assert.rejects(
+      () =>
+        settleMatch(
+          matchId,
+          [
+            { player: alice.publicKey, rank: 1 },
+            { player: alice.publicKey, rank: 1 },
+          ],
+          [10000]
+        ),
+      /DuplicatePlayer/
+    );
+
+    // Матч остался открытым, предлагаем честный результат
+    await proposeResult(matchId, [{ player: bob.publicKey, rank: 1 }], [10000]);
+
+    // remainingAccounts не совпадают с местами
+    await assert.rejects(() => finalizeResult(matchId, []), /RemainingAccountsMismatch/);
+
+    await finalizeResult(matchId, [bob.publicKey]);
+    assert.strictEqual(await balanceOf(bob.publicKey), 1100);
+  });
+
+  it("Settle can be relayed with a result signed by a registered game server", async () => {
+    const server = Keypair.generate();
+    await program.methods
+      .addGameServer(server.publicKey)
+      .accounts({ config: configPda, admin: owner } as any)
+      .rpc();
+
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+
+    const result = {
+      matchId,
+      players: [alice.publicKey, bob.publicKey],
+      placements: [{ player: bob.publicKey, rank: 1 }],
+      teams: [],
+      payoutBps: [10000],
+      outcome: { ranked: {} },
+      entryFee: new anchor.BN(100),
+      expiresAt: new anchor.BN(Math.floor(Date.now() / 1000) + 600),
+    };
+    // Подписанное сообщение: домен + borsh(MatchResult)
+    const message = Buffer.concat([
+      Buffer.from("spelltroum:match-result"),
+      program.coder.types.encode("MatchResult", result),
+    ]);
+
+    const submit = (signer: Keypair) =>
+      program.methods
+        .proposeResultSigned(result)
+        .accounts({
+          matchAccount: getMatchPda(matchId),
+          config: configPda,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        } as any)
+        .preInstructions([
+          Ed25519Program.createInstructionWithPrivateKey({ privateKey: signer.secretKey, message }),
+        ])
+        .rpc();
+
+    // Подпись незарегистрированного сервера не принимается
+    await assert.rejects(() => submit(Keypair.generate()), /UnknownGameServer/);
+
+    await submit(server);
+    await finalizeResult(matchId, [bob.publicKey]);
+    assert.strictEqual(await balanceOf(bob.publicKey), 1100);
+    assert.strictEqual(await balanceOf(alice.publicKey), 900);
+  });
+
+  it("Quorum match should settle only once M of N servers attest the same result", async () => {
+    const servers = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+    for (const server of servers) {
+      await program.methods
+        .addGameServer(server.publicKey)
+        .accounts({ config: configPda, admin: owner } as any)
+        .rpc();
+    }
+    await program.methods.setQuorum(2).accounts({ config: configPda, admin: owner } as any).rpc();
+
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createMatch(matchId, 100, { quorum: {} });
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+
+    const resultFor = (winner: PublicKey) => ({
+      matchId,
+      players: [alice.publicKey, bob.publicKey],
+      placements: [{ player: winner, rank: 1 }],
+      teams: [],
+      payoutBps: [10000],
+      outcome: { ranked: {} },
+      entryFee: new anchor.BN(100),
+      expiresAt: new anchor.BN(Math.floor(Date.now() / 1000) + 600),
+    });
+    const aliceWins = resultFor(alice.publicKey);
+    const bobWins = resultFor(bob.publicKey);
+    const attest = (server: Keypair, result: ReturnType<typeof resultFor>) =>
+      program.methods
+        .attestResult(result)
+        .accounts({
+          matchAccount: getMatchPda(matchId),
+          config: configPda,
+          server: server.publicKey,
+        } as any)
+        .signers([server])
+        .rpc();
+
+    // В quorum-режиме propose_result недоступен
+    await assert.rejects(
+      () => proposeResult(matchId, [{ player: alice.publicKey, rank: 1 }], [10000]),
+      /WrongSettlementMode/
+    );
+
+    await attest(servers[0], aliceWins);
+    await assert.rejects(() => attest(servers[0], aliceWins), /AlreadyAttested/);
+
+    // Конфликтующий результат фиксируется, но матч не рассчитывается
+    await attest(servers[1], bobWins);
+    let match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("open" in match.status, "Conflicting attestations must not propose a result");
+    assert.strictEqual(match.attestations.length, 2);
+
+    await attest(servers[2], aliceWins);
+    match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("proposed" in match.status, "Quorum should propose the result");
+
+    await finalizeResult(matchId, [alice.publicKey]);
+    assert.strictEqual(await balanceOf(alice.publicKey), 1100);
+    assert.strictEqual(await balanceOf(bob.publicKey), 900);
+  });
+
+  it("Quorum should ignore attestations of removed servers and duplicate players", async () => {
+    const servers = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+    for (const server of servers) {
+      await program.methods
+        .addGameServer(server.publicKey)
+        .accounts({ config: configPda, admin: owner } as any)
+        .rpc();
+    }
+    await program.methods.setQuorum(2).accounts({ config: configPda, admin: owner } as any).rpc();
+
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createMatch(matchId, 100, { quorum: {} });
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+
+    const resultFor = (players: PublicKey[]) => ({
+      matchId,
+      players,
+      placements: [{ player: alice.publicKey, rank: 1 }],
+      teams: [],
+      payoutBps: [10000],
+      outcome: { ranked: {} },
+      entryFee: new anchor.BN(100),
+      expiresAt: new anchor.BN(Math.floor(Date.now() / 1000) + 600),
+    });
+    const attest = (server: Keypair, result: ReturnType<typeof resultFor>) =>
+      program.methods
+        .attestResult(result)
+        .accounts({
+          matchAccount: getMatchPda(matchId),
+          config: configPda,
+          server: server.publicKey,
+        } as any)
+        .signers([server])
+        .rpc();
+
+    // Повторённый игрок не может подменить отсутствующего
+    await assert.rejects(
+      () => attest(servers[0], resultFor([alice.publicKey, alice.publicKey])),
+      /DuplicatePlayer/
+    );
+
+    const result = resultFor([alice.publicKey, bob.publicKey]);
+    await attest(servers[0], result);
+    await program.methods
+      .removeGameServer(servers[0].publicKey)
+      .accounts({ config: configPda, admin: owner } as any)
+      .rpc();
+
+    // Подтверждение удалённого сервера больше не засчитывается в кворум
+    await attest(servers[1], result);
+    let match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("open" in match.status, "A removed server must not count toward the quorum");
+    assert.strictEqual(match.attestations.length, 1);
+
+    await attest(servers[2], result);
+    match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("proposed" in match.status, "Quorum of current servers should propose the result");
+  });
+
+  it("Disputed result should stay frozen until the arbiter resolves it", async () => {
+    const setDisputeWindow = (slots: number) =>
+      program.methods
+        .setDisputeConfig(new anchor.BN(slots), owner, new anchor.BN(7 * 24 * 3600))
+        .accounts({ config: configPda, admin: owner } as any)
+        .rpc();
+    await setDisputeWindow(1000);
+
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    const outsider = await fundedPlayer(1000);
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+
+    await proposeResult(matchId, [{ player: bob.publicKey, rank: 1 }], [10000]);
+    await assert.rejects(() => finalizeResult(matchId, [bob.publicKey]), /DisputeWindowOpen/);
+
+    const evidence = Array.from(Buffer.alloc(32, 7));
+    const dispute = (disputer: Keypair) =>
+      program.methods
+        .disputeResult(matchId, evidence)
+        .accounts({ matchAccount: getMatchPda(matchId), disputer: disputer.publicKey } as any)
+        .signers([disputer])
+        .rpc();
+
+    // Спорить могут только участники матча
+    await assert.rejects(() => dispute(outsider), /NotAParticipant/);
+    await dispute(alice);
+    let match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("disputed" in match.status, "Match should be frozen by the dispute");
+    await assert.rejects(() => finalizeResult(matchId, [bob.publicKey]), /ResultUnderDispute/);
+
+    const aliceWins = {
+      outcome: { ranked: {} },
+      placements: [{ player: alice.publicKey, rank: 1 }],
+      teams: [],
+      payoutBps: [10000],
+    };
+    const resolve = (arbiter: Keypair | null, overturn: object = aliceWins) =>
+      program.methods
+        .resolveDispute(matchId, { overturn } as any)
+        .accounts({
+          matchAccount: getMatchPda(matchId),
+          config: configPda,
+          arbiter: arbiter ? arbiter.publicKey : owner,
+        } as any)
+        .signers(arbiter ? [arbiter] : [])
+        .rpc();
+    await assert.rejects(() => resolve(bob), /UnauthorizedArbiter/);
+
+    // Решение арбитра проверяется так же, как предложенный исход
+    const voidOf = (flagged: PublicKey[]) => ({ void: { flagged } });
+    await assert.rejects(
+      () => resolve(null, { outcome: voidOf([outsider.publicKey]), placements: [], teams: [], payoutBps: [] }),
+      /NotAParticipant/
+    );
+    await assert.rejects(
+      () => resolve(null, { ...aliceWins, outcome: voidOf([bob.publicKey]) }),
+      /InvalidOutcome/
+    );
+
+    // Арбитр меняет результат, решение окончательное
+    await resolve(null);
+    await assert.rejects(() => dispute(bob), /AlreadyArbitrated/);
+    await finalizeResult(matchId, [alice.publicKey]);
+
+    assert.strictEqual(await balanceOf(alice.publicKey), 1100);
+    assert.strictEqual(await balanceOf(bob.publicKey), 900);
+    match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("settled" in match.status, "Match should be settled after finalization");
+
+    await setDisputeWindow(0);
+  });
+
+  it("Dispute the arbiter never rules on should be refundable after the arbiter window", async () => {
+    const setDisputeConfig = (slots: number, arbiterWindow: number) =>
+      program.methods
+        .setDisputeConfig(new anchor.BN(slots), owner, new anchor.BN(arbiterWindow))
+        .accounts({ config: configPda, admin: owner } as any)
+        .rpc();
+    await setDisputeConfig(1000, 2);
+
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createMatch(matchId, 100, { authority: {} }, Math.floor(Date.now() / 1000) + 6);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+    await proposeResult(matchId, [{ player: bob.publicKey, rank: 1 }], [10000]);
+    await program.methods
+      .disputeResult(matchId, Array.from(Buffer.alloc(32, 1)))
+      .accounts({ matchAccount: getMatchPda(matchId), disputer: alice.publicKey } as any)
+      .signers([alice])
+      .rpc();
+
+    const expire = () =>
+      program.methods
+        .expireMatch(matchId)
+        .accounts({ matchAccount: getMatchPda(matchId), config: configPda } as any)
+        .remainingAccounts(asRemaining([alice.publicKey, bob.publicKey]))
+        .rpc();
+    // Арбитр молчит: до дедлайна + окна арбитра взносы заморожены
+    await assert.rejects(expire, /MatchNotExpired/);
+    await new Promise((resolve) => setTimeout(resolve, 10000));
+    await expire();
+
+    assert.strictEqual(await balanceOf(alice.publicKey), 1000);
+    assert.strictEqual(await balanceOf(bob.publicKey), 1000);
+    const match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("expired" in match.status);
+    assert.strictEqual(match.pendingResult, null);
+
+    await setDisputeConfig(0, 7 * 24 * 3600);
+  });
+
+  it("Team match should pay the winning team and split by member weights", async () => {
+    const matchId = `match_${Date.now()}`;
+    const players: Keypair[] = [];
+    for (let i = 0; i < 4; i++) {
+      players.push(await fundedPlayer(1000));
+    }
+    const [a1, a2, b1, b2] = players.map((p) => p.publicKey);
+    await createMatch(matchId, 100);
+    for (const player of players) {
+      await joinMatch(matchId, player);
+    }
+
+    const proposeTeams = (teams: object[]) =>
+      program.methods
+        .proposeTeamResult(matchId, teams as any, [10000])
+        .accounts({
+          matchAccount: getMatchPda(matchId),
+          config: configPda,
+          payoutPreset: null,
+          authority: owner,
+        } as any)
+        .rpc();
+
+    await assert.rejects(
+      () =>
+        proposeTeams([
+          { rank: 1, members: [a1, a2], weights: [5000] },
+          { rank: 2, members: [b1, b2], weights: [] },
+        ]),
+      /InvalidTeamWeights/
+    );
+
+    // 2v2: победители делят пул 7000/3000, проигравшие ничего не получают
+    await proposeTeams([
+      { rank: 1, members: [a1, a2], weights: [7000, 3000] },
+      { rank: 2, members: [b1, b2], weights: [] },
+    ]);
+    const match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.strictEqual(match.pendingResult.teams.length, 2);
+
+    await finalizeResult(matchId, [a1, a2, b1, b2]);
+    assert.strictEqual(await balanceOf(a1), 1180);
+    assert.strictEqual(await balanceOf(a2), 1020);
+    assert.strictEqual(await balanceOf(b1), 900);
+    assert.strictEqual(await balanceOf(b2), 900);
+  });
+
+  it("Draw, no-contest and void outcomes should split, refund or forfeit the pool", async () => {
+    const players: Keypair[] = [];
+    for (let i = 0; i < 3; i++) {
+      players.push(await fundedPlayer(1000));
+    }
+    const [a, b, c] = players.map((p) => p.publicKey);
+    const startMatch = async (matchId: string) => {
+      await createMatch(matchId, 100);
+      for (const player of players) {
+        await joinMatch(matchId, player);
+      }
+    };
+    const proposeOutcome = (matchId: string, outcome: object) =>
+      program.methods
+        .proposeOutcome(matchId, outcome as any)
+        .accounts({
+          matchAccount: getMatchPda(matchId),
+          config: configPda,
+          payoutPreset: null,
+          authority: owner,
+        } as any)
+        .rpc();
+
+    // Ничья: пул 300 делится поровну
+    const drawId = `draw_${Date.now()}`;
+    await startMatch(drawId);
+    await proposeOutcome(drawId, { draw: {} });
+    await finalizeResult(drawId, [a, b, c]);
+    for (const player of [a, b, c]) {
+      assert.strictEqual(await balanceOf(player), 1000);
+    }
+
+    // Несостоявшийся матч: полный возврат без рейка
+    const noContestId = `nocontest_${Date.now()}`;
+    await startMatch(noContestId);
+    await proposeOutcome(noContestId, { noContest: {} });
+    await finalizeResult(noContestId, [a, b, c]);
+    assert.strictEqual(await balanceOf(a), 1000);
+
+    // Аннулирование: читер теряет взнос в пользу казны, остальным возврат
+    const voidId = `void_${Date.now()}`;
+    await startMatch(voidId);
+    await assert.rejects(
+      () => proposeOutcome(voidId, { void: { flagged: [Keypair.generate().publicKey] } }),
+      /NotAParticipant/
+    );
+    await assert.rejects(() => proposeOutcome(voidId, { ranked: {} }), /InvalidOutcome/);
+    await proposeOutcome(voidId, { void: { flagged: [b] } });
+    const treasuryBefore = (await program.account.treasury.fetch(treasuryPda)).amount.toNumber();
+    await finalizeResult(voidId, [a, c]);
+    assert.strictEqual(await balanceOf(a), 1000);
+    assert.strictEqual(await balanceOf(b), 900);
+    assert.strictEqual(await balanceOf(c), 1000);
+    const treasury = await program.account.treasury.fetch(treasuryPda);
+    assert.strictEqual(treasury.amount.toNumber() - treasuryBefore, 100);
+  });
+
+  it("Variable stakes should cap the winner at a multiple of their stake and refund the rest", async () => {
+    const matchId = `stakes_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createMatch(matchId, {
+      variable: { min: new anchor.BN(50), max: new anchor.BN(500), capMultiple: 2 },
+    });
+
+    // В матче со ставками игрок ставит ровно max_entry_fee
+    await assert.rejects(() => joinMatch(matchId, alice, 600), /StakeOutOfRange/);
+    await joinMatch(matchId, alice, 100);
+    await joinMatch(matchId, bob, 300);
+    const match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok(match.escrow.eq(new anchor.BN(400)));
+
+    // Алиса выигрывает не больше 2x своей ставки, остаток 200 возвращается Бобу;
+    // балансы: сначала места, затем остальные получатели возврата
+    await proposeResult(matchId, [{ player: alice.publicKey, rank: 1 }], [10000]);
+    await finalizeResult(matchId, [alice.publicKey, bob.publicKey]);
+    assert.strictEqual(await balanceOf(alice.publicKey), 1100);
+    assert.strictEqual(await balanceOf(bob.publicKey), 900);
+  });
+
+  it("A player credited twice in one settlement should receive the net amount once", async () => {
+    const matchId = `stakes_${Date.now()}`;
+    const [alice, bob, carol] = [await fundedPlayer(1000), await fundedPlayer(1000), await fundedPlayer(1000)];
+    await createMatch(matchId, {
+      variable: { min: new anchor.BN(50), max: new anchor.BN(500), capMultiple: 2 },
+    });
+    await joinMatch(matchId, alice, 100);
+    await joinMatch(matchId, bob, 300);
+    await joinMatch(matchId, carol, 300);
+
+    // Пул 700: Алиса ограничена 200 из 420, излишек 220 делят Боб и Кэрол;
+    // Боб получает и выплату за второе место, и возврат одной записью
+    await proposeResult(
+      matchId,
+      [
+        { player: alice.publicKey, rank: 1 },
+        { player: bob.publicKey, rank: 2 },
+      ],
+      [6000, 4000]
+    );
+    await finalizeResult(matchId, [alice.publicKey, bob.publicKey, carol.publicKey]);
+    assert.strictEqual(await balanceOf(alice.publicKey), 900 + 200);
+    assert.strictEqual(await balanceOf(bob.publicKey), 700 + 280 + 110);
+    assert.strictEqual(await balanceOf(carol.publicKey), 700 + 110);
+  });
+
+  it("Settlement should reject a balance account that is not the canonical PDA", async () => {
+    // Поддельный баланс с данными игрока по чужому адресу загружается
+    // в валидатор из tests/fixtures (см. Anchor.toml)
+    const forgedBalance = new PublicKey(
+      JSON.parse(fs.readFileSync("tests/fixtures/forged-balance.json", "utf8")).pubkey
+    );
+    const alice = await fundedPlayer(
+      1000,
+      Keypair.fromSecretKey(
+        Uint8Array.from(JSON.parse(fs.readFileSync("tests/fixtures/forged-balance-owner.json", "utf8")))
+      )
+    );
+    const bob = await fundedPlayer(1000);
+    const matchId = `match_${Date.now()}`;
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+    await proposeResult(matchId, [{ player: alice.publicKey, rank: 1 }], [10000]);
+
+    const finalize = (balance: PublicKey) =>
+      program.methods
+        .finalizeResult(matchId)
+        .accounts({ matchAccount: getMatchPda(matchId), config: configPda, treasury: treasuryPda, market: null } as any)
+        .remainingAccounts(
+          [balance, getStatsPda(alice.publicKey), getStatsPda(bob.publicKey)].map((pubkey) => ({
+            pubkey,
+            isWritable: true,
+            isSigner: false,
+          }))
+        )
+        .rpc();
+    await assert.rejects(() => finalize(forgedBalance), /NonCanonicalBalanceAccount/);
+
+    await finalize(getBalancePda(alice.publicKey));
+    assert.strictEqual(await balanceOf(alice.publicKey), 1100);
+  });
+
+  it("Spectator market should pay winning bets pro rata minus the market fee", async () => {
+    await program.methods.setMarketFee(500).accounts({ config: configPda, admin: owner } as any).rpc();
+
+    const matchId = `market_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+    const market = getMarketPda(matchId);
+    await createMarket(matchId);
+
+    const [s1, s2, s3] = [await fundedPlayer(1000), await fundedPlayer(1000), await fundedPlayer(1000)];
+    await assert.rejects(() => placeBet(matchId, s1, s2.publicKey, 100), /NotAParticipant/);
+    await assert.rejects(() => placeBet(matchId, alice, bob.publicKey, 100), /PlayerCannotBet/);
+    await placeBet(matchId, s1, alice.publicKey, 300);
+    await placeBet(matchId, s2, bob.publicKey, 100);
+    await placeBet(matchId, s3, alice.publicKey, 100);
+    await assert.rejects(() => placeBet(matchId, s3, bob.publicKey, 100), /BetSelectionMismatch/);
+    assert.strictEqual(await balanceOf(s1.publicKey), 700);
+
+    // Рынок разрешается вместе с матчем и без него финализация невозможна
+    await proposeResult(matchId, [{ player: alice.publicKey, rank: 1 }], [10000]);
+    await assert.rejects(() => finalizeResult(matchId, [alice.publicKey]), /MarketMissing/);
+    const treasuryBefore = (await program.account.treasury.fetch(treasuryPda)).amount.toNumber();
+    await finalizeResult(matchId, [alice.publicKey], market);
+
+    // Пул 500, комиссия 25: ставки на Алису делят 475 пропорционально 300/100
+    for (const bettor of [s1, s2, s3]) {
+      await claimBet(matchId, bettor);
+    }
+    assert.strictEqual(await balanceOf(s1.publicKey), 700 + 356);
+    assert.strictEqual(await balanceOf(s2.publicKey), 900);
+    assert.strictEqual(await balanceOf(s3.publicKey), 900 + 118);
+    // Комиссия и остаток от округления уходят в казну
+    const treasury = await program.account.treasury.fetch(treasuryPda);
+    assert.strictEqual(treasury.amount.toNumber() - treasuryBefore, 26);
+
+    await program.methods.setMarketFee(0).accounts({ config: configPda, admin: owner } as any).rpc();
+  });
+
+  it("Bets on a player who left the lobby should be refunded", async () => {
+    const matchId = `market_lobby_${Date.now()}`;
+    const [alice, bob, carol, dave] = [
+      await fundedPlayer(1000),
+      await fundedPlayer(1000),
+      await fundedPlayer(1000),
+      await fundedPlayer(1000),
+    ];
+    await createMatch(matchId, 100, { authority: {} }, Math.floor(Date.now() / 1000) + 3600, 3);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+    await createMarket(matchId);
+    const [s1, s2, s3] = [await fundedPlayer(1000), await fundedPlayer(1000), await fundedPlayer(1000)];
+    await placeBet(matchId, s1, alice.publicKey, 100);
+    await placeBet(matchId, s2, bob.publicKey, 100);
+
+    // Боб уходит из лобби до старта, его место занимают Кэрол и Дэйв
+    await program.methods
+      .leaveMatch(matchId)
+      .accounts({
+        player: bob.publicKey,
+        matchAccount: getMatchPda(matchId),
+        playerBalance: getBalancePda(bob.publicKey),
+      } as any)
+      .signers([bob])
+      .rpc();
+    await joinMatch(matchId, carol);
+    await placeBet(matchId, s3, carol.publicKey, 100);
+    await joinMatch(matchId, dave);
+
+    await proposeResult(matchId, [{ player: carol.publicKey, rank: 1 }], [10000]);
+    await finalizeResult(matchId, [carol.publicKey], getMarketPda(matchId));
+    for (const bettor of [s1, s2, s3]) {
+      await claimBet(matchId, bettor);
+    }
+
+    // Ставка на ушедшего Боба возвращается и не входит в пул победителей
+    assert.strictEqual(await balanceOf(s1.publicKey), 900);
+    assert.strictEqual(await balanceOf(s2.publicKey), 1000);
+    assert.strictEqual(await balanceOf(s3.publicKey), 1100);
+  });
+
+  it("A bettor should not be able to join the match they bet on", async () => {
+    const matchId = `market_join_${Date.now()}`;
+    const [alice, bob, spectator] = [await fundedPlayer(1000), await fundedPlayer(1000), await fundedPlayer(1000)];
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await createMarket(matchId);
+    await placeBet(matchId, spectator, alice.publicKey, 100);
+
+    // Зритель поставил на Алису и мог бы сдать ей игру
+    await assert.rejects(() => joinMatch(matchId, spectator), /BettorCannotJoin/);
+    // Без рынка и ставки вход в матч с рынком невозможен
+    await assert.rejects(
+      async () =>
+        program.methods
+          .joinMatch(matchId, new anchor.BN(100), await nonceOf(bob.publicKey))
+          .accounts({
+            signer: bob.publicKey,
+            player: bob.publicKey,
+            sessionKey: null,
+            matchAccount: getMatchPda(matchId),
+            playerBalance: getBalancePda(bob.publicKey),
+            market: null,
+            bet: null,
+          } as any)
+          .signers([bob])
+          .rpc(),
+      /MarketMissing/
+    );
+    await joinMatch(matchId, bob);
+    const match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.strictEqual(match.players.length, 2);
+  });
+
+  it("Settlement should record every player's stats", async () => {
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createStats(alice.publicKey);
+    await createStats(bob.publicKey);
+    const play = async (matchId: string, winner: Keypair) => {
+      await createMatch(matchId, 100);
+      await joinMatch(matchId, alice);
+      await joinMatch(matchId, bob);
+      await settleMatch(matchId, [{ player: winner.publicKey, rank: 1 }], [10000]);
+    };
+
+    await play(`stats_${Date.now()}`, alice);
+    await play(`stats_${Date.now()}_2`, alice);
+    await play(`stats_${Date.now()}_3`, bob);
+
+    const aliceStats = await program.account.playerStats.fetch(getStatsPda(alice.publicKey));
+    assert.strictEqual(aliceStats.matchesPlayed, 3);
+    assert.strictEqual(aliceStats.wins, 2);
+    assert.strictEqual(aliceStats.totalWagered.toNumber(), 300);
+    assert.strictEqual(aliceStats.totalWon.toNumber(), 400);
+    assert.strictEqual(aliceStats.biggestWin.toNumber(), 200);
+    // Серия прервана поражением, лучшая серия сохраняется
+    assert.strictEqual(aliceStats.currentStreak, 0);
+    assert.strictEqual(aliceStats.bestStreak, 2);
+
+    let bobStats = await program.account.playerStats.fetch(getStatsPda(bob.publicKey));
+    assert.strictEqual(bobStats.wins, 1);
+    assert.strictEqual(bobStats.currentStreak, 1);
+
+    // Ничья засчитывается как сыгранный матч, но доля пула не выигрыш;
+    // игрок без статистики пропускается, его PDA всё равно передаётся
+    const carol = await fundedPlayer(1000);
+    const drawId = `stats_${Date.now()}_draw`;
+    await createMatch(drawId, 100);
+    for (const player of [alice, bob, carol]) {
+      await joinMatch(drawId, player);
+    }
+    await program.methods
+      .proposeOutcome(drawId, { draw: {} } as any)
+      .accounts({ matchAccount: getMatchPda(drawId), config: configPda, payoutPreset: null, authority: owner } as any)
+      .rpc();
+    await finalizeResult(drawId, [alice.publicKey, bob.publicKey, carol.publicKey]);
+
+    bobStats = await program.account.playerStats.fetch(getStatsPda(bob.publicKey));
+    assert.strictEqual(bobStats.matchesPlayed, 4);
+    assert.strictEqual(bobStats.totalWagered.toNumber(), 400);
+    assert.strictEqual(bobStats.totalWon.toNumber(), 200);
+    assert.strictEqual(bobStats.currentStreak, 0);
+    assert.strictEqual(await provider.connection.getAccountInfo(getStatsPda(carol.publicKey)), null);
+  });
+
+  it("Joining the same match twice should fail with PlayerAlreadyJoined", async () => {
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await assert.rejects(() => joinMatch(matchId, alice), /PlayerAlreadyJoined/);
+  });
+
+  it("Join should respect the player's maximum entry fee and nonce", async () => {
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    await createMatch(matchId, 100);
+
+    await assert.rejects(() => joinMatch(matchId, alice, 99), /EntryFeeAboveMax/);
+    await assert.rejects(
+      () =>
+        program.methods
+          .joinMatch(matchId, new anchor.BN(100), new anchor.BN(5))
+          .accounts({
+            signer: alice.publicKey,
+            player: alice.publicKey,
+            sessionKey: null,
+            matchAccount: getMatchPda(matchId),
+            playerBalance: getBalancePda(alice.publicKey),
+            market: null,
+            bet: null,
+          } as any)
+          .signers([alice])
+          .rpc(),
+      /InvalidNonce/
+    );
+
+    await joinMatch(matchId, alice, 100);
+    assert.ok((await nonceOf(alice.publicKey)).eq(new anchor.BN(1)), "Nonce should be consumed");
+  });
+
+  it("Backend can join a player with an off-chain signed consent", async () => {
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    await createMatch(matchId, 100);
+
+    const consent = {
+      matchId,
+      player: alice.publicKey,
+      maxEntryFee: new anchor.BN(100),
+      nonce: await nonceOf(alice.publicKey),
+    };
+    const message = Buffer.concat([
+      Buffer.from("spelltroum:entry-consent"),
+      program.coder.types.encode("EntryConsent", consent),
+    ]);
+    const submit = (signer: Keypair) =>
+      program.methods
+        .joinMatchWithConsent(consent)
+        .accounts({
+          matchAccount: getMatchPda(matchId),
+          playerBalance: getBalancePda(alice.publicKey),
+          market: null,
+          bet: null,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        } as any)
+        .preInstructions([
+          Ed25519Program.createInstructionWithPrivateKey({ privateKey: signer.secretKey, message }),
+        ])
+        .rpc();
+
+    // Согласие, подписанное не игроком, отклоняется
+    await assert.rejects(() => submit(Keypair.generate()), /InvalidConsent/);
+
+    await submit(alice);
+    assert.strictEqual(await balanceOf(alice.publicKey), 900);
+
+    // Повторно то же согласие использовать нельзя
+    await assert.rejects(() => submit(alice), /PlayerAlreadyJoined|InvalidNonce/);
+  });
+
+  it("Session key can join within its spend cap but never withdraw", async () => {
+    const alice = await fundedPlayer(1000);
+    const session = Keypair.generate();
+    const [sessionPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("session"), alice.publicKey.toBuffer(), session.publicKey.toBuffer()],
+      program.programId
+    );
+    const expiresAt = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+    await program.methods
+      .createSessionKey(session.publicKey, PublicKey.default, expiresAt, new anchor.BN(150), { joinMatches: {} })
+      .accounts({ owner: alice.publicKey, sessionKey: sessionPda } as any)
+      .signers([alice])
+      .rpc();
+
+    const joinWithSession = async (matchId: string) =>
+      program.methods
+        .joinMatch(matchId, new anchor.BN(100), await nonceOf(alice.publicKey))
+        .accounts({
+          signer: session.publicKey,
+          player: alice.publicKey,
+          sessionKey: sessionPda,
+          matchAccount: getMatchPda(matchId),
+          playerBalance: getBalancePda(alice.publicKey),
+          market: null,
+          bet: null,
+        } as any)
+        .signers([session])
+        .rpc();
+
+    const first = `match_${Date.now()}`;
+    await createMatch(first, 100);
+    await joinWithSession(first);
+    assert.strictEqual(await balanceOf(alice.publicKey), 900);
+
+    // Второй вход превысил бы лимит трат сессии (200 > 150)
+    const second = `match_${Date.now()}_2`;
+    await createMatch(second, 100);
+    await assert.rejects(() => joinWithSession(second), /SessionSpendCapExceeded/);
+
+    // Сессионный ключ не может выводить средства
+    await assert.rejects(
+      () =>
+        program.methods
+          .withdraw(new anchor.BN(100))
+          .accounts({
+            owner: session.publicKey,
+            playerBalance: getBalancePda(alice.publicKey),
+            vault: vaultPda,
+            destination: alice.publicKey,
+            systemProgram: SystemProgram.programId,
+          } as any)
+          .signers([session])
+          .rpc()
+    );
+
+    // После отзыва ключ больше не работает
+    await program.methods
+      .revokeSessionKey()
+      .accounts({ owner: alice.publicKey, sessionKey: sessionPda } as any)
+      .signers([alice])
+      .rpc();
+    await assert.rejects(() => joinWithSession(second));
+  });
+
+  it("Session key should register for tournaments and leagues within its spend cap", async () => {
+    const alice = await fundedPlayer(1000);
+    const session = Keypair.generate();
+    const [sessionPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("session"), alice.publicKey.toBuffer(), session.publicKey.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .createSessionKey(
+        session.publicKey,
+        PublicKey.default,
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        new anchor.BN(150),
+        { joinMatches: {} }
+      )
+      .accounts({ owner: alice.publicKey, sessionKey: sessionPda } as any)
+      .signers([alice])
+      .rpc();
+
+    const tournamentId = `cup_${Date.now()}`;
+    const [tournamentPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tournament"), Buffer.from(anchor.utils.sha256.hash(tournamentId), "hex")],
+      program.programId
+    );
+    await program.methods
+      .createTournament(tournamentId, PublicKey.default, new anchor.BN(100), 8, [10000])
+      .accounts({ tournament: tournamentPda, config: configPda, treasury: treasuryPda, authority: owner } as any)
+      .rpc();
+    await program.methods
+      .registerTournament(tournamentId, new anchor.BN(100), await nonceOf(alice.publicKey))
+      .accounts({
+        signer: session.publicKey,
+        player: alice.publicKey,
+        sessionKey: sessionPda,
+        tournament: tournamentPda,
+        playerBalance: getBalancePda(alice.publicKey),
+      } as any)
+      .signers([session])
+      .rpc();
+    assert.strictEqual(await balanceOf(alice.publicKey), 900);
+
+    // Бай-ин лиги вместе с турниром превысил бы лимит сессии (200 > 150)
+    const leagueId = `league_${Date.now()}`;
+    const [leaguePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("league"), Buffer.from(anchor.utils.sha256.hash(leagueId), "hex")],
+      program.programId
+    );
+    await program.methods
+      .createLeague(leagueId, PublicKey.default, new anchor.BN(100), 8, { roundRobin: {} }, [10000])
+      .accounts({ league: leaguePda, config: configPda, treasury: treasuryPda, authority: owner } as any)
+      .rpc();
+    await assert.rejects(
+      async () =>
+        program.methods
+          .registerLeague(leagueId, new anchor.BN(100), await nonceOf(alice.publicKey))
+          .accounts({
+            signer: session.publicKey,
+            player: alice.publicKey,
+            sessionKey: sessionPda,
+            league: leaguePda,
+            playerBalance: getBalancePda(alice.publicKey),
+          } as any)
+          .signers([session])
+          .rpc(),
+      /SessionSpendCapExceeded/
+    );
+  });
+
+  it("Sit-and-go lobby should start when the last seat fills and refund leavers before that", async () => {
+    const matchId = `lobby_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createMatch(matchId, 100, { authority: {} }, Math.floor(Date.now() / 1000) + 3600, 2);
+
+    const leave = (player: Keypair) =>
+      program.methods
+        .leaveMatch(matchId)
+        .accounts({
+          player: player.publicKey,
+          matchAccount: getMatchPda(matchId),
+          playerBalance: getBalancePda(player.publicKey),
+        } as any)
+        .signers([player])
+        .rpc();
+
+    // До заполнения лобби можно выйти с полным возвратом взноса
+    await joinMatch(matchId, alice);
+    await leave(alice);
+    assert.strictEqual(await balanceOf(alice.publicKey), 1000);
+
+    await joinMatch(matchId, alice);
+    await assert.rejects(
+      () => settleMatch(matchId, [{ player: alice.publicKey, rank: 1 }], [10000]),
+      /LobbyNotStarted/
+    );
+
+    await joinMatch(matchId, bob);
+    const match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("started" in match.status, "Full lobby should start by itself");
+    await assert.rejects(() => leave(bob), /MatchNotOpen/);
+
+    await settleMatch(matchId, [{ player: bob.publicKey, rank: 1 }], [10000]);
+    assert.strictEqual(await balanceOf(bob.publicKey), 1100);
+    assert.strictEqual(await balanceOf(alice.publicKey), 900);
+  });
+
+  it("Cancel should refund every player", async () => {
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+
+    await createMatch(matchId, 250);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+
+    await program.methods
+      .cancelMatch(matchId)
+      .accounts({ matchAccount: getMatchPda(matchId), config: configPda, authority: owner } as any)
+      .remainingAccounts(asRemaining([alice.publicKey, bob.publicKey]))
+      .rpc();
+
+    assert.strictEqual(await balanceOf(alice.publicKey), 1000);
+    assert.strictEqual(await balanceOf(bob.publicKey), 1000);
+    const match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("cancelled" in match.status, "Match should be marked as cancelled");
+    await assert.rejects(() => joinMatch(matchId, alice), /MatchNotOpen/);
+  });
+
+  it("Anyone can refund the players once the match deadline has passed", async () => {
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+
+    await createMatch(matchId, 250, { authority: {} }, Math.floor(Date.now() / 1000) + 3);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+
+    // expire_match не требует прав, но работает только после дедлайна
+    const expire = () =>
+      program.methods
+        .expireMatch(matchId)
+        .accounts({ matchAccount: getMatchPda(matchId), config: configPda } as any)
+        .remainingAccounts(asRemaining([alice.publicKey, bob.publicKey]))
+        .rpc();
+    await assert.rejects(expire, /MatchNotExpired/);
+
+    await new Promise((resolve) => setTimeout(resolve, 5000));
+    await expire();
+
+    assert.strictEqual(await balanceOf(alice.publicKey), 1000);
+    assert.strictEqual(await balanceOf(bob.publicKey), 1000);
+    const match = await program.account.match.fetch(getMatchPda(matchId));
+    assert.ok("expired" in match.status, "Match should be marked as expired");
+    await assert.rejects(
+      () => settleMatch(matchId, [{ player: alice.publicKey, rank: 1 }], [10000]),
+      /MatchNotOpen/
+    );
+  });
+
+  it("Tournament bracket should advance winners through byes and pay the top placements", async () => {
+    const tournamentId = `cup_${Date.now()}`;
+    const [tournamentPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tournament"), Buffer.from(anchor.utils.sha256.hash(tournamentId), "hex")],
+      program.programId
+    );
+    const players: Keypair[] = [];
+    for (let i = 0; i < 5; i++) {
+      players.push(await fundedPlayer(1000));
+    }
+    const [p0, p1, p2, p3, p4] = players.map((p) => p.publicKey);
+
+    await program.methods
+      .createTournament(tournamentId, PublicKey.default, new anchor.BN(100), 8, [6000, 3000, 1000])
+      .accounts({ tournament: tournamentPda, config: configPda, treasury: treasuryPda, authority: owner } as any)
+      .rpc();
+    for (const player of players) {
+      await program.methods
+        .registerTournament(tournamentId, new anchor.BN(100), await nonceOf(player.publicKey))
+        .accounts({
+          signer: player.publicKey,
+          player: player.publicKey,
+          sessionKey: null,
+          tournament: tournamentPda,
+          playerBalance: getBalancePda(player.publicKey),
+        } as any)
+        .signers([player])
+        .rpc();
+    }
+
+    const manage = { tournament: tournamentPda, config: configPda, authority: owner } as any;
+    await program.methods.startTournament(tournamentId).accounts(manage).rpc();
+
+    // 5 игроков в сетке на 8 мест: p1, p2 и p3 проходят дальше без соперника
+    let tournament = await program.account.tournament.fetch(tournamentPda);
+    assert.deepStrictEqual(
+      tournament.winners.map((w) => w.toBase58()),
+      [PublicKey.default, p1, p2, p3].map((w) => w.toBase58())
+    );
+
+    const report = (round: number, index: number, winner: PublicKey) =>
+      program.methods.reportTournamentMatch(tournamentId, round, index, winner).accounts(manage).rpc();
+    await assert.rejects(() => report(0, 1, p2), /BracketMatchReported/);
+    await report(0, 0, p0);
+    await report(1, 0, p0);
+    await assert.rejects(() => report(1, 1, p0), /WinnerNotInMatch/);
+    await report(1, 1, p2);
+    await report(2, 0, p0);
+
+    // Проигравшие в полуфинале делят третье место
+    tournament = await program.account.tournament.fetch(tournamentPda);
+    assert.ok("finished" in tournament.status, "Tournament should be finished after the final");
+    assert.deepStrictEqual(
+      tournament.placements.map((p) => [p.player.toBase58(), p.rank]),
+      [
+        [p0.toBase58(), 1],
+        [p2.toBase58(), 2],
+        [p1.toBase58(), 3],
+        [p3.toBase58(), 3],
+        [p4.toBase58(), 5],
+      ]
+    );
+
+    await program.methods
+      .payoutTournament(tournamentId)
+      .accounts({ tournament: tournamentPda, config: configPda, treasury: treasuryPda } as any)
+      .remainingAccounts(asRemaining([p0, p2, p1, p3]))
+      .rpc();
+
+    // Пул 500: 60% / 30% / 10% на двоих за третье место
+    assert.strictEqual(await balanceOf(p0), 1200);
+    assert.strictEqual(await balanceOf(p2), 1050);
+    assert.strictEqual(await balanceOf(p1), 925);
+    assert.strictEqual(await balanceOf(p3), 925);
+    assert.strictEqual(await balanceOf(p4), 900);
+  });
+
+  it("Swiss league should pair by score and break ties with Buchholz", async () => {
+    const leagueId = `league_${Date.now()}`;
+    const [leaguePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("league"), Buffer.from(anchor.utils.sha256.hash(leagueId), "hex")],
+      program.programId
+    );
+    const players: Keypair[] = [];
+    for (let i = 0; i < 4; i++) {
+      players.push(await fundedPlayer(1000));
+    }
+    const [p0, p1, p2, p3] = players.map((p) => p.publicKey);
+
+    await program.methods
+      .createLeague(leagueId, PublicKey.default, new anchor.BN(100), 4, { swiss: { rounds: 2 } } as any, [
+        7000, 3000,
+      ])
+      .accounts({ league: leaguePda, config: configPda, treasury: treasuryPda, authority: owner } as any)
+      .rpc();
+    for (const player of players) {
+      await program.methods
+        .registerLeague(leagueId, new anchor.BN(100), await nonceOf(player.publicKey))
+        .accounts({
+          signer: player.publicKey,
+          player: player.publicKey,
+          sessionKey: null,
+          league: leaguePda,
+          playerBalance: getBalancePda(player.publicKey),
+        } as any)
+        .signers([player])
+        .rpc();
+    }
+
+    const manage = { league: leaguePda, config: configPda, authority: owner } as any;
+    const pairings = async (round: number) =>
+      (await program.account.league.fetch(leaguePda)).games
+        .filter((g) => g.round === round)
+        .map((g) => [g.home, g.away]);
+    const report = (round: number, results: object[]) =>
+      program.methods.reportLeagueRound(leagueId, round, results as any).accounts(manage).rpc();
+
+    await program.methods.startLeague(leagueId).accounts(manage).rpc();
+    assert.deepStrictEqual(await pairings(0), [
+      [0, 1],
+      [2, 3],
+    ]);
+
+    await assert.rejects(() => report(0, [{ homeWin: {} }]), /ResultCountMismatch/);
+    await report(0, [{ homeWin: {} }, { draw: {} }]);
+
+    // Во втором туре лидер играет со следующим по очкам соперником
+    assert.deepStrictEqual(await pairings(1), [
+      [0, 2],
+      [3, 1],
+    ]);
+    await report(1, [{ draw: {} }, { draw: {} }]);
+
+    // p2 и p3 набрали по 2 очка, Бухгольц p2 выше (6 против 3)
+    const league = await program.account.league.fetch(leaguePda);
+    assert.ok("finished" in league.status, "League should be finished after the last round");
+    assert.deepStrictEqual(league.points, [4, 1, 2, 2]);
+    assert.deepStrictEqual(league.buchholz, [3, 6, 6, 3]);
+    assert.deepStrictEqual(
+      league.placements.map((p) => [p.player.toBase58(), p.rank]),
+      [
+        [p0.toBase58(), 1],
+        [p2.toBase58(), 2],
+        [p3.toBase58(), 3],
+        [p1.toBase58(), 4],
+      ]
+    );
+
+    await program.methods
+      .payoutLeague(leagueId)
+      .accounts({ league: leaguePda, config: configPda, treasury: treasuryPda } as any)
+      .remainingAccounts(asRemaining([p0, p2]))
+      .rpc();
+    assert.strictEqual(await balanceOf(p0), 1180);
+    assert.strictEqual(await balanceOf(p2), 1020);
+    assert.strictEqual(await balanceOf(p3), 900);
+  });
+
+  it("Round robin league should give every player one bye in an odd field", async () => {
+    const leagueId = `league_rr_${Date.now()}`;
+    const [leaguePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("league"), Buffer.from(anchor.utils.sha256.hash(leagueId), "hex")],
+      program.programId
+    );
+    const players: Keypair[] = [];
+    for (let i = 0; i < 5; i++) {
+      players.push(await fundedPlayer(1000));
+    }
+    const [p0, p1, p2, p3, p4] = players.map((p) => p.publicKey);
+
+    await program.methods
+      .createLeague(leagueId, PublicKey.default, new anchor.BN(100), 5, { roundRobin: {} } as any, [
+        6000, 3000, 1000,
+      ])
+      .accounts({ league: leaguePda, config: configPda, treasury: treasuryPda, authority: owner } as any)
+      .rpc();
+    for (const player of players) {
+      await program.methods
+        .registerLeague(leagueId, new anchor.BN(100), await nonceOf(player.publicKey))
+        .accounts({
+          signer: player.publicKey,
+          player: player.publicKey,
+          sessionKey: null,
+          league: leaguePda,
+          playerBalance: getBalancePda(player.publicKey),
+        } as any)
+        .signers([player])
+        .rpc();
+    }
+
+    const manage = { league: leaguePda, config: configPda, authority: owner } as any;
+    const report = (round: number, results: object[]) =>
+      program.methods.reportLeagueRound(leagueId, round, results as any).accounts(manage).rpc();
+
+    // Пять игроков: четыре соперника и один пропуск тура, итого пять туров
+    await program.methods.startLeague(leagueId).accounts(manage).rpc();
+    assert.strictEqual((await program.account.league.fetch(leaguePda)).totalRounds, 5);
+
+    // Результаты в порядке пар тура, пропуски не сообщаются:
+    // 0: [0 bye] 1-4 2-3, 1: 0-1 [2 bye] 3-4, 2: 0-2 3-1 [4 bye],
+    // 3: 0-3 4-2 [1 bye], 4: 0-4 [3 bye] 1-2
+    const rounds = [
+      [{ homeWin: {} }, { homeWin: {} }],
+      [{ homeWin: {} }, { draw: {} }],
+      [{ homeWin: {} }, { awayWin: {} }],
+      [{ homeWin: {} }, { awayWin: {} }],
+      [{ draw: {} }, { homeWin: {} }],
+    ];
+    for (const [round, results] of rounds.entries()) {
+      await report(round, results);
+    }
+
+    const league = await program.account.league.fetch(leaguePda);
+    assert.ok("finished" in league.status, "League should be finished after the last round");
+
+    // Каждая пара сыграна ровно один раз, у каждого игрока ровно один пропуск
+    const BYE = 255;
+    const pairs = league.games
+      .filter((g) => g.away !== BYE)
+      .map((g) => [Math.min(g.home, g.away), Math.max(g.home, g.away)].join("-"))
+      .sort();
+    const expected: string[] = [];
+    for (let a = 0; a < 5; a++) {
+      for (let b = a + 1; b < 5; b++) {
+        expected.push(`${a}-${b}`);
+      }
+    }
+    assert.deepStrictEqual(pairs, expected);
+    const byes = league.games.filter((g) => g.away === BYE);
+    assert.deepStrictEqual(byes.map((g) => g.home).sort(), [0, 1, 2, 3, 4]);
+    assert.ok(byes.every((g) => "bye" in g.result));
+
+    // Пропуск приносит 3 очка, Бухгольц считается только по сыгранным партиям
+    assert.deepStrictEqual(league.points, [13, 12, 9, 4, 5]);
+    assert.deepStrictEqual(league.buchholz, [30, 31, 34, 39, 38]);
+    assert.deepStrictEqual(
+      league.placements.map((p) => [p.player.toBase58(), p.rank]),
+      [
+        [p0.toBase58(), 1],
+        [p1.toBase58(), 2],
+        [p2.toBase58(), 3],
+        [p4.toBase58(), 4],
+        [p3.toBase58(), 5],
+      ]
+    );
+
+    await program.methods
+      .payoutLeague(leagueId)
+      .accounts({ league: leaguePda, config: configPda, treasury: treasuryPda } as any)
+      .remainingAccounts(asRemaining([p0, p1, p2]))
+      .rpc();
+    assert.strictEqual(await balanceOf(p0), 1200);
+    assert.strictEqual(await balanceOf(p1), 1050);
+    assert.strictEqual(await balanceOf(p2), 950);
+    assert.strictEqual(await balanceOf(p3), 900);
+    assert.strictEqual(await balanceOf(p4), 900);
+  });
+
+  it("Settle should credit the house rake to the treasury", async () => {
+    // 10% рейка
+    await program.methods
+      .setFee(1000, owner)
+      .accounts({ config: configPda, admin: owner } as any)
+      .rpc();
+
+    const matchId = `match_${Date.now()}`;
+    const alice = await fundedPlayer(1000);
+    const bob = await fundedPlayer(1000);
+    await createMatch(matchId, 100);
+    await joinMatch(matchId, alice);
+    await joinMatch(matchId, bob);
+
+    const treasuryBefore = (await program.account.treasury.fetch(treasuryPda)).amount.toNumber();
+    await settleMatch(matchId, [{ player: alice.publicKey, rank: 1 }], [10000]);
+
+    assert.strictEqual(await balanceOf(alice.publicKey), 1080);
+    const treasury = await program.account.treasury.fetch(treasuryPda);
+    assert.strictEqual(treasury.amount.toNumber() - treasuryBefore, 20);
+
+    // Снять комиссию может только fee authority
+    await program.methods
+      .withdrawTreasury(new anchor.BN(20))
+      .accounts({
+        config: configPda,
+        feeAuthority: owner,
+        treasury: treasuryPda,
+        vault: vaultPda,
+        destination: owner,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .rpc();
+    assert.strictEqual(
+      (await program.account.treasury.fetch(treasuryPda)).amount.toNumber(),
+      treasuryBefore
+    );
+
+    await program.methods
+      .setFee(0, owner)
+      .accounts({ config: configPda, admin: owner } as any)
+      .rpc();
+  });
+
+  it("SPL Token deposits should settle in their own mint and withdraw through the vault", async () => {
+    const payer = (provider.wallet as anchor.Wallet).payer;
+    const mint = await createMint(provider.connection, payer, owner, null, 0, undefined, undefined, TOKEN_PROGRAM_ID);
+    const alice = await tokenHolder(mint, TOKEN_PROGRAM_ID, 1000);
+    const bob = await tokenHolder(mint, TOKEN_PROGRAM_ID, 1000);
+    for (const { player, tokenAccount } of [alice, bob]) {
+      await depositToken(player, mint, tokenAccount, TOKEN_PROGRAM_ID, 1000);
+    }
+    assert.strictEqual(await tokenBalanceOf(alice.player.publicKey, mint), 1000);
+    assert.strictEqual(await tokenAmount(getTokenVaultPda(mint), TOKEN_PROGRAM_ID), 2000);
+
+    // 10% рейка, матч во взносах этого минта
+    await program.methods.setFee(1000, owner).accounts({ config: configPda, admin: owner } as any).rpc();
+    const [tokenTreasury] = PublicKey.findProgramAddressSync(
+      [Buffer.from("treasury"), mint.toBuffer()],
+      program.programId
+    );
+    const matchId = `token_${Date.now()}`;
+    await program.methods
+      .createMatch(
+        matchId,
+        mint,
+        { fixed: { entryFee: new anchor.BN(100) } } as any,
+        { authority: {} } as any,
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        0
+      )
+      .accounts({
+        matchAccount: getMatchPda(matchId),
+        config: configPda,
+        treasury: tokenTreasury,
+        authority: owner,
+        systemProgram: SystemProgram.programId,
+      } as any)
+      .rpc();
+    await joinMatch(matchId, alice.player, 100, mint);
+    await joinMatch(matchId, bob.player, 100, mint);
+    await proposeResult(matchId, [{ player: alice.player.publicKey, rank: 1 }], [10000]);
+
+    const finalize = (balance: PublicKey) =>
+      program.methods
+        .finalizeResult(matchId)
+        .accounts({ matchAccount: getMatchPda(matchId), config: configPda, treasury: tokenTreasury, market: null } as any)
+        .remainingAccounts(
+          [balance, getStatsPda(alice.player.publicKey, mint), getStatsPda(bob.player.publicKey, mint)].map(
+            (pubkey) => ({ pubkey, isWritable: true, isSigner: false })
+          )
+        )
+        .rpc();
+    // SOL-баланс победителя не подходит для выплаты в токенах
+    await assert.rejects(() => finalize(getBalancePda(alice.player.publicKey)), /MintMismatch/);
+    await finalize(getBalancePda(alice.player.publicKey, mint));
+    assert.strictEqual(await tokenBalanceOf(alice.player.publicKey, mint), 1080);
+    assert.strictEqual(await tokenBalanceOf(bob.player.publicKey, mint), 900);
+    assert.strictEqual((await program.account.treasury.fetch(tokenTreasury)).amount.toNumber(), 20);
+
+    // Вывод идёт из токен-аккаунта хранилища с подписью vault PDA
+    await withdrawToken(alice.player, mint, alice.tokenAccount, TOKEN_PROGRAM_ID, 1080);
+    assert.strictEqual(await tokenAmount(alice.tokenAccount, TOKEN_PROGRAM_ID), 1080);
+    assert.strictEqual(await tokenBalanceOf(alice.player.publicKey, mint), 0);
+
+    const ownerTokenAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mint,
+      owner,
+      undefined,
+      TOKEN_PROGRAM_ID
+    );
+    await program.methods
+      .withdrawTreasuryToken(new anchor.BN(20))
+      .accounts({
+        config: configPda,
+        feeAuthority: owner,
+        mint,
+        treasury: tokenTreasury,
+        vault: vaultPda,
+        vaultTokenAccount: getTokenVaultPda(mint),
+        destination: ownerTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .rpc();
+    assert.strictEqual(await tokenAmount(ownerTokenAccount, TOKEN_PROGRAM_ID), 20);
+    assert.strictEqual(await tokenAmount(getTokenVaultPda(mint), TOKEN_PROGRAM_ID), 900);
+
+    await program.methods.setFee(0, owner).accounts({ config: configPda, admin: owner } as any).rpc();
+  });
+
+  it("Token-2022 deposits should credit only what arrives after the transfer fee", async () => {
+    // Минт Token-2022 с комиссией за перевод 1%
+    const mintKeypair = Keypair.generate();
+    const mint = mintKeypair.publicKey;
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: owner,
+          newAccountPubkey: mint,
+          space: mintLen,
+          lamports: await provider.connection.getMinimumBalanceForRentExemption(mintLen),
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(mint, owner, owner, 100, BigInt(1_000_000), TOKEN_2022_PROGRAM_ID),
+        createInitializeMintInstruction(mint, 0, owner, null, TOKEN_2022_PROGRAM_ID)
+      ),
+      [mintKeypair]
+    );
+
+    const alice = await tokenHolder(mint, TOKEN_2022_PROGRAM_ID, 1000);
+    await depositToken(alice.player, mint, alice.tokenAccount, TOKEN_2022_PROGRAM_ID, 1000);
+    // Из 1000 до хранилища дошло 990, зачисляется только это
+    assert.strictEqual(await tokenBalanceOf(alice.player.publicKey, mint), 990);
+    assert.strictEqual(await tokenAmount(getTokenVaultPda(mint), TOKEN_2022_PROGRAM_ID), 990);
+
+    // При выводе комиссию снова удерживает минт: списано 500, получено 495
+    await withdrawToken(alice.player, mint, alice.tokenAccount, TOKEN_2022_PROGRAM_ID, 500);
+    assert.strictEqual(await tokenBalanceOf(alice.player.publicKey, mint), 490);
+    assert.strictEqual(await tokenAmount(alice.tokenAccount, TOKEN_2022_PROGRAM_ID), 495);
+    assert.strictEqual(await tokenAmount(getTokenVaultPda(mint), TOKEN_2022_PROGRAM_ID), 490);
+  });
 });
\ No newline at end of file